@@ -0,0 +1,30 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use nagrs::nagios::NagiosStatus;
+
+/// testdata/status.dat repeated with renamed hosts, `hosts` hosts with
+/// seven services each.
+fn status_dat(hosts: usize) -> String {
+    let status = std::fs::read_to_string("testdata/status.dat").unwrap();
+    (0..hosts)
+        .map(|i| status.replace("localhost", &format!("host{:05}", i)))
+        .collect()
+}
+
+/// `bytes` converts on multiple threads when run with `--features parallel`.
+fn parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    for hosts in [100, 1000] {
+        let status = status_dat(hosts);
+        group.throughput(Throughput::Bytes(status.len() as u64));
+        group.bench_with_input(BenchmarkId::new("reader", hosts), &status, |b, status| {
+            b.iter(|| NagiosStatus::parse_reader(status.as_bytes()).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("bytes", hosts), &status, |b, status| {
+            b.iter(|| NagiosStatus::parse_bytes(status.as_bytes()).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, parse);
+criterion_main!(benches);
@@ -1,9 +1,10 @@
+use nagrs::nagios::cmd::NagiosCmd;
 use nagrs::Nagrs;
 
 fn main() {
     let nagrs = Nagrs::new("testdata/nagios.cmd", "testdata/status.dat");
-    let cmd = nagrs::nagios::cmd::DisableHostgroupHostChecks {
+    let cmd: Box<dyn NagiosCmd> = Box::new(nagrs::nagios::cmd::DisableHostgroupHostChecks {
         hostgroup_name: "localhost".to_string(),
-    };
-    nagrs.write_cmds(&vec![Box::new(cmd)]).unwrap();
+    });
+    nagrs.write_cmds(&[cmd]).unwrap();
 }
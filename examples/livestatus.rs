@@ -0,0 +1,14 @@
+use nagrs::server::LivestatusServer;
+use nagrs::Nagrs;
+
+fn main() {
+    let command_file_path = "/usr/local/nagios/var/rw/nagios.cmd";
+    let status_file_path = "/usr/local/nagios/var/status.dat";
+    let socket_path = "/usr/local/nagios/var/rw/live";
+    let nagrs = Nagrs::new(command_file_path, status_file_path);
+
+    LivestatusServer::new(nagrs, socket_path)
+        .with_error_handler(|error| eprintln!("livestatus: {}", error))
+        .run()
+        .unwrap();
+}
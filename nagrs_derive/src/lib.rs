@@ -27,12 +27,16 @@ fn impl_nagios_cmd_macro(ast: &syn::DeriveInput) -> TokenStream {
 
     let gen = quote! {
         impl NagiosCmd for #name {
-            fn to_cmd_string(&self) -> String {
-                let mut command_string: String = #upper_snake.to_string();
-                #(
-                    command_string.push_str(format!(";{}", self.#field_name).as_str());
-                )*
-                command_string
+            fn name(&self) -> &str {
+                #upper_snake
+            }
+
+            fn args(&self) -> Vec<(&str, String)> {
+                vec![
+                    #(
+                        (stringify!(#field_name), self.#field_name.to_string()),
+                    )*
+                ]
             }
         }
     };
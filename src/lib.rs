@@ -1,17 +1,97 @@
 use anyhow::Result;
 use chrono::Utc;
+use nagios::audit::{AuditContext, AuditLog, AuditRecord, AuditStatus};
 use nagios::cmd::NagiosCmd;
-use std::io::BufWriter;
+use nagios::pipe::{BatchWriteError, CommandPipe};
+use nagios::policy::{CommandPolicy, PolicyError};
+use nagios::watch::Watcher;
+use std::fmt;
 use std::path::Path;
+use std::time::Duration;
+use thiserror::Error;
 
 use nagios::NagiosStatus;
 
 pub mod nagios;
+pub mod server;
+
+#[derive(Error, Debug)]
+pub enum WriteError {
+    #[error("{0}")]
+    Pipe(#[from] BatchWriteError),
+    #[error("{0}")]
+    Policy(#[from] PolicyError),
+    /// The audit log could not be opened, so no command was sent.
+    #[error("failed to open audit log, no command sent: {0}")]
+    Audit(#[source] std::io::Error),
+    /// The commands were processed, `written` of them sent to Nagios, but
+    /// their records could not be appended to the audit log.
+    #[error("{written} of {total} commands sent but not recorded in the audit log: {source}")]
+    Unaudited {
+        written: usize,
+        total: usize,
+        #[source]
+        source: std::io::Error,
+    },
+}
+
+/// Lets callers that propagate `write_cmds` errors as `io::Result` keep
+/// using `?`. The `WriteError` is kept as the inner error.
+impl From<WriteError> for std::io::Error {
+    fn from(error: WriteError) -> Self {
+        use nagios::pipe::PipeError;
+        use std::io::ErrorKind;
+
+        let kind = match &error {
+            WriteError::Pipe(BatchWriteError { error, .. }) => match error {
+                PipeError::NotFound(_) => ErrorKind::NotFound,
+                PipeError::NotAFifo(_) | PipeError::CommandTooLong(_) => ErrorKind::InvalidInput,
+                PipeError::NagiosNotRunning(_) => ErrorKind::NotConnected,
+                PipeError::Timeout(_) => ErrorKind::TimedOut,
+                PipeError::Io(error) => error.kind(),
+            },
+            WriteError::Policy(_) => ErrorKind::PermissionDenied,
+            WriteError::Audit(source) | WriteError::Unaudited { source, .. } => source.kind(),
+        };
+        std::io::Error::new(kind, error)
+    }
+}
+
+/// A failed `write_cmds_with_undo`, with the batch that undoes the commands
+/// written before the failure.
+#[derive(Error)]
+#[error("{error}")]
+pub struct UndoWriteError {
+    #[source]
+    pub error: WriteError,
+    /// Empty if no command was written.
+    pub undo: Vec<Box<dyn NagiosCmd>>,
+}
+
+impl fmt::Debug for UndoWriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UndoWriteError")
+            .field("error", &self.error)
+            .field(
+                "undo",
+                &self
+                    .undo
+                    .iter()
+                    .map(|cmd| cmd.to_cmd_string())
+                    .collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
 
 #[derive(Debug)]
 pub struct Nagrs<P: AsRef<Path>> {
     command_file_path: P,
     status_file_path: P,
+    write_timeout: Duration,
+    audit_log: Option<AuditLog>,
+    dry_run: bool,
+    policy: Option<CommandPolicy>,
 }
 
 impl<P: AsRef<Path>> Nagrs<P> {
@@ -19,22 +99,402 @@ impl<P: AsRef<Path>> Nagrs<P> {
         Nagrs {
             command_file_path,
             status_file_path,
+            write_timeout: nagios::pipe::DEFAULT_WRITE_TIMEOUT,
+            audit_log: None,
+            dry_run: false,
+            policy: None,
         }
     }
 
+    /// Maximum time `write_cmds` waits for Nagios to drain the command pipe.
+    pub fn with_write_timeout(mut self, write_timeout: Duration) -> Nagrs<P> {
+        self.write_timeout = write_timeout;
+        self
+    }
+
+    /// Records every command passed to `write_cmds` in `audit_log`.
+    pub fn with_audit_log(mut self, audit_log: AuditLog) -> Nagrs<P> {
+        self.audit_log = Some(audit_log);
+        self
+    }
+
+    /// In dry-run mode `write_cmds` renders and audits commands but never
+    /// touches the command file.
+    pub fn with_dry_run(mut self, dry_run: bool) -> Nagrs<P> {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Consults `policy` before every `write_cmds`; a denied batch is not
+    /// written at all.
+    pub fn with_policy(mut self, policy: CommandPolicy) -> Nagrs<P> {
+        self.policy = Some(policy);
+        self
+    }
+
     pub fn parse(&self) -> Result<NagiosStatus> {
         NagiosStatus::parse_file(&self.status_file_path)
     }
 
+    /// Reports changes between successive parses of status.dat.
+    pub fn watcher(&self) -> Watcher {
+        Watcher::new(&self.status_file_path)
+    }
+
+    /// Renders `cmds` into the lines `write_cmds` would write.
+    pub fn render_cmds(&self, cmds: &[Box<dyn NagiosCmd>], timestamp: i64) -> Vec<String> {
+        cmds.iter()
+            .map(|cmd| {
+                let mut line = Vec::new();
+                nagios::cmd::write_cmd_line(cmd.as_ref(), timestamp, &mut line)
+                    .expect("writing to a Vec never fails");
+                String::from_utf8_lossy(&line).into_owned()
+            })
+            .collect()
+    }
+
     /// cmd
-    pub fn write_cmds(&self, cmds: &Vec<Box<dyn NagiosCmd>>) -> std::io::Result<()> {
+    ///
+    /// The error converts into `io::Error`, so `?` still works in functions
+    /// returning `io::Result`.
+    pub fn write_cmds(&self, cmds: &[Box<dyn NagiosCmd>]) -> Result<(), WriteError> {
+        self.write_cmds_audited(cmds, &AuditContext::default())
+    }
+
+    /// Same as `write_cmds`, additionally returning the batch that restores
+    /// every host and service touched by `cmds` to its state in `snapshot`.
+    /// If only part of `cmds` was written, the error carries the batch that
+    /// undoes that part.
+    pub fn write_cmds_with_undo(
+        &self,
+        cmds: &[Box<dyn NagiosCmd>],
+        snapshot: &NagiosStatus,
+    ) -> Result<Vec<Box<dyn NagiosCmd>>, UndoWriteError> {
+        match self.write_batch(cmds, &AuditContext::default()) {
+            Ok(()) => Ok(nagios::undo::undo_cmds(snapshot, cmds)),
+            Err((error, written)) => Err(UndoWriteError {
+                error,
+                undo: nagios::undo::undo_cmds(snapshot, &cmds[..written]),
+            }),
+        }
+    }
+
+    /// Same as `write_cmds`, recording `context` in the audit log.
+    pub fn write_cmds_audited(
+        &self,
+        cmds: &[Box<dyn NagiosCmd>],
+        context: &AuditContext,
+    ) -> Result<(), WriteError> {
+        self.write_batch(cmds, context).map_err(|(error, _)| error)
+    }
+
+    /// Writes and audits `cmds`. On failure, also returns how many of `cmds`
+    /// (counted from the start) reached the command file.
+    fn write_batch(
+        &self,
+        cmds: &[Box<dyn NagiosCmd>],
+        context: &AuditContext,
+    ) -> Result<(), (WriteError, usize)> {
         let timestamp = Utc::now().timestamp();
-        let file = std::fs::OpenOptions::new()
-            .append(true)
-            .open(&self.command_file_path)?;
-        let mut writer = BufWriter::new(file);
-        cmds.iter()
-            .try_for_each(|cmd| nagios::cmd::write_cmd_line(cmd, timestamp, &mut writer))?;
-        Ok(())
+        // open the audit log first, so that a log that cannot be opened stops
+        // the batch; records are appended once the outcome is known
+        let mut audit_writer = match &self.audit_log {
+            Some(audit_log) => Some(
+                audit_log
+                    .open()
+                    .map_err(|error| (WriteError::Audit(error), 0))?,
+            ),
+            None => None,
+        };
+
+        let admitted = match &self.policy {
+            Some(policy) if self.dry_run => policy.check(cmds),
+            Some(policy) => policy.admit(cmds),
+            None => Ok(()),
+        };
+
+        let (result, written) = match admitted {
+            Err(error) => (Err(WriteError::from(error)), 0),
+            Ok(_) if self.dry_run => (Ok(()), 0),
+            Ok(_) => {
+                let result = CommandPipe::new(&self.command_file_path)
+                    .with_timeout(self.write_timeout)
+                    .write_cmds(cmds, timestamp);
+                let written = match &result {
+                    Ok(_) => cmds.len(),
+                    Err(error) => error.written,
+                };
+                (result.map_err(WriteError::from), written)
+            }
+        };
+
+        if let Some(audit_writer) = &mut audit_writer {
+            let records: Vec<AuditRecord> = cmds
+                .iter()
+                .zip(self.render_cmds(cmds, timestamp))
+                .enumerate()
+                .map(|(i, (cmd, line))| AuditRecord {
+                    time: Utc::now(),
+                    user: context.user.clone(),
+                    reason: context.reason.clone(),
+                    command: cmd.to_cmd_string(),
+                    line,
+                    status: match (&result, self.dry_run, i < written) {
+                        (Err(WriteError::Policy(_)), _, _) => AuditStatus::Denied,
+                        (_, true, _) => AuditStatus::DryRun,
+                        (_, false, true) => AuditStatus::Written,
+                        (_, false, false) => AuditStatus::Failed,
+                    },
+                })
+                .collect();
+            audit_writer.append(&records).map_err(|source| {
+                let error = WriteError::Unaudited {
+                    written,
+                    total: cmds.len(),
+                    source,
+                };
+                (error, written)
+            })?;
+        }
+
+        result.map_err(|error| (error, written))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nagios::cmd::{DisableHostCheck, DisableHostNotifications, DisableSvcCheck};
+    use nagios::policy::Rule;
+    use regex::Regex;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    fn cmds() -> Vec<Box<dyn NagiosCmd>> {
+        vec![
+            Box::new(DisableHostCheck {
+                host_name: "web01".to_string(),
+            }),
+            Box::new(DisableHostNotifications {
+                host_name: "web01".to_string(),
+            }),
+        ]
+    }
+
+    fn context() -> AuditContext {
+        AuditContext {
+            user: "nagiosadmin".to_string(),
+            reason: "CHG-1234".to_string(),
+        }
+    }
+
+    /// Creates a command pipe at `path` and opens its reading end.
+    fn command_pipe(path: &Path) -> std::fs::File {
+        let c_path = std::ffi::CString::new(path.to_str().unwrap()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) }, 0);
+        std::fs::OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(path)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_render_cmds() {
+        let nagrs = Nagrs::new("nagios.cmd", "status.dat");
+        assert_eq!(
+            nagrs.render_cmds(&cmds(), 1647824400),
+            vec![
+                "[1647824400] DISABLE_HOST_CHECK;web01\n",
+                "[1647824400] DISABLE_HOST_NOTIFICATIONS;web01\n",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_write_cmds_dry_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let command_file_path = dir.path().join("nagios.cmd");
+        let audit_log = AuditLog::new(dir.path().join("audit.jsonl"));
+        let nagrs = Nagrs::new(command_file_path.clone(), dir.path().join("status.dat"))
+            .with_audit_log(audit_log.clone())
+            .with_dry_run(true);
+
+        nagrs.write_cmds_audited(&cmds(), &context()).unwrap();
+
+        assert!(!command_file_path.exists());
+        let records = audit_log.read().unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].user, "nagiosadmin");
+        assert_eq!(records[0].reason, "CHG-1234");
+        assert_eq!(records[0].command, "DISABLE_HOST_CHECK;web01");
+        assert!(records
+            .iter()
+            .all(|record| record.status == AuditStatus::DryRun));
+    }
+
+    #[test]
+    fn test_write_cmds_io_error() {
+        fn write(nagrs: &Nagrs<std::path::PathBuf>) -> std::io::Result<()> {
+            let cmds = cmds();
+            nagrs.write_cmds(&cmds)?;
+            Ok(())
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let nagrs = Nagrs::new(dir.path().join("nagios.cmd"), dir.path().join("status.dat"));
+        let error = write(&nagrs).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+        assert!(matches!(
+            error.get_ref().unwrap().downcast_ref::<WriteError>(),
+            Some(WriteError::Pipe(_))
+        ));
+    }
+
+    #[test]
+    fn test_write_cmds_denied_by_policy() {
+        let dir = tempfile::tempdir().unwrap();
+        let audit_log = AuditLog::new(dir.path().join("audit.jsonl"));
+        let policy = CommandPolicy::allow_by_default()
+            .deny(Rule::new().commands(Regex::new("_NOTIFICATIONS$").unwrap()));
+        let nagrs = Nagrs::new(dir.path().join("nagios.cmd"), dir.path().join("status.dat"))
+            .with_audit_log(audit_log.clone())
+            .with_policy(policy);
+
+        let result = nagrs.write_cmds_audited(&cmds(), &context());
+        assert!(matches!(
+            result,
+            Err(WriteError::Policy(PolicyError::Denied(_)))
+        ));
+
+        let records = audit_log.read().unwrap();
+        assert_eq!(records.len(), 2);
+        assert!(records
+            .iter()
+            .all(|record| record.status == AuditStatus::Denied));
+    }
+
+    #[test]
+    fn test_write_cmds_with_undo() {
+        let dir = tempfile::tempdir().unwrap();
+        let nagrs = Nagrs::new(dir.path().join("nagios.cmd"), dir.path().join("status.dat"))
+            .with_dry_run(true);
+        let snapshot = NagiosStatus::parse_file("testdata/status.dat").unwrap();
+        let cmds: Vec<Box<dyn NagiosCmd>> = vec![Box::new(DisableHostCheck {
+            host_name: "localhost".to_string(),
+        })];
+
+        let undo = nagrs.write_cmds_with_undo(&cmds, &snapshot).unwrap();
+        assert_eq!(
+            undo.iter()
+                .map(|cmd| cmd.to_cmd_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "ENABLE_HOST_CHECK;localhost",
+                "ENABLE_HOST_NOTIFICATIONS;localhost",
+                "ENABLE_HOST_EVENT_HANDLER;localhost",
+                "ENABLE_HOST_FLAP_DETECTION;localhost",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_write_cmds_with_undo_partial() {
+        let dir = tempfile::tempdir().unwrap();
+        let command_file_path = dir.path().join("nagios.cmd");
+        // keep the reader open but never read, so the pipe fills up
+        let _reader = command_pipe(&command_file_path);
+        let nagrs = Nagrs::new(command_file_path, dir.path().join("status.dat"))
+            .with_write_timeout(Duration::from_millis(50));
+        let snapshot = NagiosStatus::parse_file("testdata/status.dat").unwrap();
+
+        // the first command is written, the last one is not
+        let mut cmds: Vec<Box<dyn NagiosCmd>> = vec![Box::new(DisableHostCheck {
+            host_name: "localhost".to_string(),
+        })];
+        cmds.extend((0..10000).map(|i| {
+            Box::new(DisableHostCheck {
+                host_name: format!("web{:05}", i),
+            }) as Box<dyn NagiosCmd>
+        }));
+        cmds.push(Box::new(DisableSvcCheck {
+            host_name: "localhost".to_string(),
+            service_description: "HTTP".to_string(),
+        }));
+
+        let Err(error) = nagrs.write_cmds_with_undo(&cmds, &snapshot) else {
+            panic!("the batch does not fit in the pipe");
+        };
+        assert!(matches!(&error.error, WriteError::Pipe(error) if error.written > 0));
+        assert_eq!(
+            error
+                .undo
+                .iter()
+                .map(|cmd| cmd.to_cmd_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "ENABLE_HOST_CHECK;localhost",
+                "ENABLE_HOST_NOTIFICATIONS;localhost",
+                "ENABLE_HOST_EVENT_HANDLER;localhost",
+                "ENABLE_HOST_FLAP_DETECTION;localhost",
+            ]
+        );
+
+        // nothing written, nothing to undo
+        let nagrs = nagrs.with_policy(CommandPolicy::deny_by_default());
+        let Err(error) = nagrs.write_cmds_with_undo(&cmds, &snapshot) else {
+            panic!("the policy denies the batch");
+        };
+        assert!(matches!(error.error, WriteError::Policy(_)));
+        assert!(error.undo.is_empty());
+    }
+
+    #[test]
+    fn test_write_cmds_audits_failure() {
+        let dir = tempfile::tempdir().unwrap();
+        let audit_log = AuditLog::new(dir.path().join("audit.jsonl"));
+        let nagrs = Nagrs::new(dir.path().join("nagios.cmd"), dir.path().join("status.dat"))
+            .with_audit_log(audit_log.clone());
+
+        let result = nagrs.write_cmds_audited(&cmds(), &context());
+        assert!(matches!(result, Err(WriteError::Pipe(_))));
+
+        let records = audit_log.read().unwrap();
+        assert_eq!(records.len(), 2);
+        assert!(records
+            .iter()
+            .all(|record| record.status == AuditStatus::Failed));
+    }
+
+    #[test]
+    fn test_write_cmds_audit_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let command_file_path = dir.path().join("nagios.cmd");
+        let mut reader = command_pipe(&command_file_path);
+
+        // a log that cannot be opened stops the batch
+        let nagrs = Nagrs::new(command_file_path.clone(), dir.path().join("status.dat"))
+            .with_audit_log(AuditLog::new(dir.path().join("missing/audit.jsonl")));
+        let error = nagrs.write_cmds(&cmds()).unwrap_err();
+        assert!(matches!(error, WriteError::Audit(_)));
+        assert!(error.to_string().contains("no command sent"));
+
+        // a log that cannot be appended to is reported after sending
+        let nagrs = Nagrs::new(command_file_path, dir.path().join("status.dat"))
+            .with_audit_log(AuditLog::new("/dev/full"));
+        let error = nagrs.write_cmds(&cmds()).unwrap_err();
+        assert!(matches!(
+            error,
+            WriteError::Unaudited {
+                written: 2,
+                total: 2,
+                ..
+            }
+        ));
+        assert!(error
+            .to_string()
+            .starts_with("2 of 2 commands sent but not recorded in the audit log: "));
+        let mut buf = String::new();
+        std::io::Read::read_to_string(&mut reader, &mut buf).unwrap();
+        assert_eq!(buf.lines().count(), 2);
     }
 }
@@ -1,16 +1,36 @@
+pub mod audit;
 mod block;
+mod borrowed;
+pub mod cache;
 pub mod cmd;
+pub mod diff;
+pub mod drift;
+mod index;
+pub mod livestatus;
 pub mod object;
+pub mod output;
+pub mod perfdata;
+pub mod pipe;
+pub mod policy;
+pub mod problem;
+pub mod query;
+pub mod spool;
+pub mod stream;
+pub mod summary;
+pub mod undo;
+pub mod watch;
 
-use anyhow::{anyhow, Result};
+use anyhow::Result;
 use regex::Regex;
 use std::collections::HashMap;
-use std::fs::File;
-use std::io;
+use std::fs;
+use std::io::Read;
+use std::iter;
 use std::path::Path;
 
-use self::block::{Block, BlockType, ParseError};
-use self::object::{Host, Service};
+use self::index::Index;
+use self::object::{Comment, Downtime, Host, Service};
+use self::stream::{StatusObject, StatusObjects, StreamError};
 
 #[derive(Debug)]
 pub struct NagiosStatus {
@@ -19,18 +39,36 @@ pub struct NagiosStatus {
     hosts: HashMap<String, Host>,
     services: HashMap<String, Vec<Service>>,
     contacts: Vec<HashMap<String, String>>,
+    comments: Vec<Comment>,
+    downtimes: Vec<Downtime>,
+    index: Index,
 }
 
 impl NagiosStatus {
     pub fn parse_file<P: AsRef<Path>>(path: P) -> Result<NagiosStatus> {
-        let file = File::open(path)?;
-        let buf = io::BufReader::new(file);
-        let blocks = Block::to_blocks(buf);
-        Self::from_blocks(blocks)
+        Self::parse_bytes(&fs::read(path)?)
     }
 
-    fn from_blocks<I: Iterator<Item = Result<Block, ParseError>>>(
-        blocks: I,
+    /// Parses status.dat held in memory. Invalid UTF-8 is replaced as in
+    /// `String::from_utf8_lossy`. With the `parallel` feature, blocks of large
+    /// files are converted on multiple threads.
+    pub fn parse_bytes(bytes: &[u8]) -> Result<NagiosStatus> {
+        let text = String::from_utf8_lossy(bytes);
+        #[cfg(feature = "parallel")]
+        let objects = borrowed::par_objects(&text)?.into_iter().map(Ok);
+        #[cfg(not(feature = "parallel"))]
+        let objects = borrowed::BufferObjects::new(&text);
+        Self::from_objects(objects)
+    }
+
+    /// Parses status.dat from `reader` a line at a time, for when the whole
+    /// file should not be held in memory at once.
+    pub fn parse_reader<R: Read>(reader: R) -> Result<NagiosStatus> {
+        Self::from_objects(StatusObjects::new(reader))
+    }
+
+    fn from_objects<I: Iterator<Item = Result<StatusObject, StreamError>>>(
+        objects: I,
     ) -> Result<NagiosStatus> {
         let mut status = NagiosStatus {
             info: HashMap::new(),
@@ -38,40 +76,36 @@ impl NagiosStatus {
             hosts: HashMap::new(),
             services: HashMap::new(),
             contacts: Vec::new(),
+            comments: Vec::new(),
+            downtimes: Vec::new(),
+            index: Index::default(),
         };
 
-        for block in blocks {
-            match block {
-                Ok(block) => match &block.block_type {
-                    BlockType::Info => {
-                        status.info = block.key_values;
-                    }
-                    BlockType::Program => {
-                        status.program = block.key_values;
-                    }
-                    BlockType::Host => {
-                        let host = Host::try_from(block.key_values)?;
-                        status.hosts.insert(host.host_name.to_owned(), host);
-                    }
-                    BlockType::Service => {
-                        let service = Service::try_from(block.key_values)?;
-                        let host_services = status.services.get_mut(&service.host_name);
-                        match host_services {
-                            Some(host_service) => host_service.push(service),
-                            None => {
-                                status
-                                    .services
-                                    .insert(service.host_name.to_string(), vec![service]);
-                            }
+        for object in objects {
+            match object? {
+                StatusObject::Info(info) => status.info = info,
+                StatusObject::Program(program) => status.program = program,
+                StatusObject::Host(host) => {
+                    status.hosts.insert(host.host_name.to_owned(), host);
+                }
+                StatusObject::Service(service) => {
+                    let host_services = status.services.get_mut(&service.host_name);
+                    match host_services {
+                        Some(host_service) => host_service.push(service),
+                        None => {
+                            status
+                                .services
+                                .insert(service.host_name.to_string(), vec![service]);
                         }
                     }
-                    BlockType::Contact => status.contacts.push(block.key_values),
-                    _ => {}
-                },
-                Err(error) => return Err(anyhow!("{}", error)),
+                }
+                StatusObject::Contact(contact) => status.contacts.push(contact),
+                StatusObject::Comment(comment) => status.comments.push(comment),
+                StatusObject::Downtime(downtime) => status.downtimes.push(downtime),
             }
         }
 
+        status.reindex();
         Ok(status)
     }
 
@@ -83,21 +117,288 @@ impl NagiosStatus {
         &self.program
     }
 
+    pub fn get_contacts(&self) -> &[HashMap<String, String>] {
+        &self.contacts
+    }
+
+    pub fn get_comments(&self) -> &[Comment] {
+        &self.comments
+    }
+
+    pub fn get_downtimes(&self) -> &[Downtime] {
+        &self.downtimes
+    }
+
     pub fn get_host(&self, host_name: &str) -> Option<Host> {
-        self.hosts.get(host_name).map(|host| host.clone())
+        self.host(host_name).cloned()
     }
 
     pub fn get_host_services(&self, host_name: &str) -> Option<Vec<Service>> {
-        self.services
-            .get(host_name)
-            .map(|services| services.clone())
+        self.services.get(host_name).cloned()
+    }
+
+    pub fn get_service(&self, host_name: &str, service_description: &str) -> Option<Service> {
+        self.service(host_name, service_description).cloned()
     }
 
     pub fn get_hosts_regex(&self, re: &Regex) -> Vec<Host> {
-        self.hosts
+        self.hosts()
+            .filter(|host| re.is_match(&host.host_name))
+            .cloned()
+            .collect()
+    }
+
+    /// Services whose host name matches `host_re` and whose description
+    /// matches `service_re`, sorted by host name, then description.
+    pub fn get_services_regex(&self, host_re: &Regex, service_re: &Regex) -> Vec<Service> {
+        let mut services = self
+            .services
             .iter()
-            .filter(|(host_name, _)| re.is_match(&host_name))
-            .map(|(_, host)| host.clone())
+            .filter(|(host_name, _)| host_re.is_match(host_name))
+            .flat_map(|(_, services)| services)
+            .filter(|service| service_re.is_match(&service.service_description))
+            .collect::<Vec<_>>();
+        services.sort_by(|a, b| {
+            (&a.host_name, &a.service_description).cmp(&(&b.host_name, &b.service_description))
+        });
+        services.into_iter().cloned().collect()
+    }
+
+    /// The service named `service_description` on every host that has one,
+    /// sorted by host name.
+    pub fn get_services_by_description(&self, service_description: &str) -> Vec<Service> {
+        let mut host_names = self.services.keys().collect::<Vec<_>>();
+        host_names.sort();
+        host_names
+            .into_iter()
+            .filter_map(|host_name| self.service(host_name, service_description))
+            .cloned()
             .collect()
     }
+
+    ////////////////////////////////////
+    // borrowing accessors
+
+    pub fn host(&self, host_name: &str) -> Option<&Host> {
+        self.hosts.get(host_name)
+    }
+
+    /// All hosts, in no particular order.
+    pub fn hosts(&self) -> impl Iterator<Item = &Host> {
+        self.hosts.values()
+    }
+
+    /// The services of `host_name`, empty if it has none.
+    pub fn host_services(&self, host_name: &str) -> &[Service] {
+        self.services.get(host_name).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn service(&self, host_name: &str, service_description: &str) -> Option<&Service> {
+        let i = self
+            .index
+            .service_position(host_name, service_description)?;
+        self.services.get(host_name)?.get(i)
+    }
+
+    /// All services, in no particular order.
+    pub fn services(&self) -> impl Iterator<Item = &Service> {
+        self.services.values().flatten()
+    }
+
+    ////////////////////////////////////
+    // matching snapshots
+
+    /// The hosts and services of `self` and `other` matched by name, sorted
+    /// by host name, each host before its services sorted by description.
+    fn matched<'a>(&'a self, other: &'a NagiosStatus) -> impl Iterator<Item = Matched<'a>> {
+        let mut host_names = self
+            .hosts
+            .keys()
+            .chain(other.hosts.keys())
+            .chain(self.services.keys())
+            .chain(other.services.keys())
+            .collect::<Vec<_>>();
+        host_names.sort();
+        host_names.dedup();
+
+        host_names.into_iter().flat_map(move |host_name| {
+            let mut service_descriptions = self
+                .host_services(host_name)
+                .iter()
+                .chain(other.host_services(host_name))
+                .map(|service| &service.service_description)
+                .collect::<Vec<_>>();
+            service_descriptions.sort();
+            service_descriptions.dedup();
+
+            let host = Matched::Host {
+                host_name,
+                old: self.host(host_name),
+                new: other.host(host_name),
+            };
+            let services = service_descriptions
+                .into_iter()
+                .map(move |service_description| Matched::Service {
+                    host_name,
+                    service_description,
+                    old: self.service(host_name, service_description),
+                    new: other.service(host_name, service_description),
+                });
+            iter::once(host).chain(services)
+        })
+    }
+}
+
+/// A host or service in two snapshots, `None` in a snapshot without it.
+enum Matched<'a> {
+    Host {
+        host_name: &'a str,
+        old: Option<&'a Host>,
+        new: Option<&'a Host>,
+    },
+    Service {
+        host_name: &'a str,
+        service_description: &'a str,
+        old: Option<&'a Service>,
+        new: Option<&'a Service>,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status() -> NagiosStatus {
+        NagiosStatus::parse_file("testdata/status.dat").unwrap()
+    }
+
+    #[test]
+    fn test_service() {
+        let status = status();
+        struct TestCase<'a>(&'a str, &'a str, bool);
+        let test_cases = vec![
+            TestCase("localhost", "HTTP", true),
+            TestCase("localhost", "Swap Usage", true),
+            TestCase("localhost", "SSH", false),
+            TestCase("otherhost", "HTTP", false),
+        ];
+        for test_case in test_cases {
+            let service = status.service(test_case.0, test_case.1);
+            assert_eq!(service.is_some(), test_case.2);
+            if let Some(service) = service {
+                assert_eq!(service.service_description, test_case.1);
+            }
+            assert_eq!(
+                status.get_service(test_case.0, test_case.1).as_ref(),
+                service
+            );
+        }
+    }
+
+    #[test]
+    fn test_borrowing_accessors() {
+        let status = status();
+        assert_eq!(status.hosts().count(), 1);
+        assert_eq!(status.services().count(), 7);
+        assert_eq!(status.host("localhost").unwrap().host_name, "localhost");
+        assert!(status.host("otherhost").is_none());
+        assert_eq!(status.host_services("localhost").len(), 7);
+        assert!(status.host_services("otherhost").is_empty());
+        assert!(status.services().all(|service| status
+            .service(&service.host_name, &service.service_description)
+            == Some(service)));
+    }
+
+    /// The testdata with a second host, `webhost`, running HTTP and PING.
+    fn two_host_status() -> NagiosStatus {
+        let mut status = status();
+        let mut host = status.hosts["localhost"].clone();
+        host.host_name = "webhost".to_string();
+        status.hosts.insert(host.host_name.clone(), host);
+        let services = status.services["localhost"]
+            .iter()
+            .filter(|service| ["PING", "HTTP"].contains(&service.service_description.as_str()))
+            .map(|service| Service {
+                host_name: "webhost".to_string(),
+                ..service.clone()
+            })
+            .collect();
+        status.services.insert("webhost".to_string(), services);
+        status.reindex();
+        status
+    }
+
+    #[test]
+    fn test_get_services_regex() {
+        let status = two_host_status();
+        struct TestCase<'a>(&'a str, &'a str, Vec<(&'a str, &'a str)>);
+        let test_cases = vec![
+            TestCase(
+                ".*",
+                "^(HTTP|PING)$",
+                vec![
+                    ("localhost", "HTTP"),
+                    ("localhost", "PING"),
+                    ("webhost", "HTTP"),
+                    ("webhost", "PING"),
+                ],
+            ),
+            TestCase("^web", ".*", vec![("webhost", "HTTP"), ("webhost", "PING")]),
+            TestCase(
+                "^local",
+                "^Current",
+                vec![
+                    ("localhost", "Current Load"),
+                    ("localhost", "Current Users"),
+                ],
+            ),
+            TestCase("^web", "^Swap", vec![]),
+            TestCase("^nohost$", ".*", vec![]),
+        ];
+        for test_case in test_cases {
+            let services = status.get_services_regex(
+                &Regex::new(test_case.0).unwrap(),
+                &Regex::new(test_case.1).unwrap(),
+            );
+            assert_eq!(
+                services
+                    .iter()
+                    .map(|service| (
+                        service.host_name.as_str(),
+                        service.service_description.as_str()
+                    ))
+                    .collect::<Vec<_>>(),
+                test_case.2,
+                "{} {}",
+                test_case.0,
+                test_case.1
+            );
+        }
+    }
+
+    #[test]
+    fn test_get_services_by_description() {
+        let status = two_host_status();
+        struct TestCase<'a>(&'a str, Vec<&'a str>);
+        let test_cases = vec![
+            TestCase("HTTP", vec!["localhost", "webhost"]),
+            TestCase("Swap Usage", vec!["localhost"]),
+            // descriptions are matched exactly
+            TestCase("HTTP ", vec![]),
+            TestCase("http", vec![]),
+        ];
+        for test_case in test_cases {
+            let services = status.get_services_by_description(test_case.0);
+            assert_eq!(
+                services
+                    .iter()
+                    .map(|service| service.host_name.as_str())
+                    .collect::<Vec<_>>(),
+                test_case.1
+            );
+            assert!(services
+                .iter()
+                .all(|service| service.service_description == test_case.0));
+        }
+    }
 }
@@ -0,0 +1,145 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+////////////////////////////////////
+// audit record
+
+/// Who sends a batch of commands and why.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditContext {
+    pub user: String,
+    pub reason: String,
+}
+
+impl Default for AuditContext {
+    /// The login name of the current process and an empty reason.
+    fn default() -> Self {
+        let user = std::env::var("USER")
+            .or_else(|_| std::env::var("LOGNAME"))
+            .unwrap_or_else(|_| "unknown".to_string());
+        AuditContext {
+            user,
+            reason: String::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditStatus {
+    Written,
+    Failed,
+    Denied,
+    DryRun,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub time: DateTime<Utc>,
+    pub user: String,
+    pub reason: String,
+    pub command: String,
+    pub line: String,
+    pub status: AuditStatus,
+}
+
+////////////////////////////////////
+// audit log
+
+/// Append-only JSON lines file of `AuditRecord`s.
+#[derive(Debug, Clone)]
+pub struct AuditLog {
+    path: PathBuf,
+}
+
+impl AuditLog {
+    pub fn new<P: AsRef<Path>>(path: P) -> AuditLog {
+        AuditLog {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    pub fn open(&self) -> io::Result<AuditWriter> {
+        let file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&self.path)?;
+        Ok(AuditWriter { file })
+    }
+
+    pub fn read(&self) -> io::Result<Vec<AuditRecord>> {
+        let file = File::open(&self.path)?;
+        io::BufReader::new(file)
+            .lines()
+            .map(|line| {
+                serde_json::from_str(&line?)
+                    .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+            })
+            .collect()
+    }
+}
+
+pub struct AuditWriter {
+    file: File,
+}
+
+impl AuditWriter {
+    /// Appends `records` with a single write so that concurrent writers do
+    /// not interleave within a batch.
+    pub fn append(&mut self, records: &[AuditRecord]) -> io::Result<()> {
+        let mut buf = Vec::new();
+        for record in records {
+            serde_json::to_writer(&mut buf, record)?;
+            buf.push(b'\n');
+        }
+        self.file.write_all(&buf)?;
+        self.file.sync_data()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn record(command: &str, status: AuditStatus) -> AuditRecord {
+        AuditRecord {
+            time: Utc.timestamp_opt(1647824400, 0).unwrap(),
+            user: "nagiosadmin".to_string(),
+            reason: "maintenance".to_string(),
+            command: command.to_string(),
+            line: format!("[1647824400] {}\n", command),
+            status,
+        }
+    }
+
+    #[test]
+    fn test_record_json() {
+        let json = serde_json::to_string(&record("DISABLE_HOST_CHECK;web01", AuditStatus::DryRun))
+            .unwrap();
+        assert_eq!(
+            json,
+            r#"{"time":"2022-03-21T01:00:00Z","user":"nagiosadmin","reason":"maintenance","command":"DISABLE_HOST_CHECK;web01","line":"[1647824400] DISABLE_HOST_CHECK;web01\n","status":"dry_run"}"#
+        );
+    }
+
+    #[test]
+    fn test_append() {
+        let dir = tempfile::tempdir().unwrap();
+        let audit_log = AuditLog::new(dir.path().join("audit.jsonl"));
+
+        let first = vec![record("DISABLE_HOST_CHECK;web01", AuditStatus::Written)];
+        let second = vec![
+            record("ENABLE_HOST_CHECK;web01", AuditStatus::Written),
+            record("ENABLE_HOST_CHECK;web02", AuditStatus::Failed),
+        ];
+        audit_log.open().unwrap().append(&first).unwrap();
+        audit_log.open().unwrap().append(&second).unwrap();
+
+        let records = audit_log.read().unwrap();
+        assert_eq!(records, [first, second].concat());
+    }
+}
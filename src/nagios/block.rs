@@ -19,6 +19,10 @@ pub enum BlockType {
     Host,
     Service,
     Contact,
+    HostComment,
+    ServiceComment,
+    HostDowntime,
+    ServiceDowntime,
     Unkown,
 }
 
@@ -57,17 +61,15 @@ impl<'a> Block {
             .map(|byteline| {
                 let byteline = byteline.unwrap();
                 match std::str::from_utf8(&byteline) {
-                    Ok(s) => {
-                        s.to_string()
-                    },
+                    Ok(s) => s.to_string(),
                     Err(_) => {
                         let cow = String::from_utf8_lossy(&byteline);
                         cow.to_string()
-                    },
+                    }
                 }
             })
             .map(|line| line.trim().to_owned())
-            .filter(|line| line.len() > 0)
+            .filter(|line| !line.is_empty())
             .filter(|line| line.chars().nth(0).unwrap() != '#');
 
         let lines = Lines {
@@ -78,13 +80,17 @@ impl<'a> Block {
     }
 }
 
-fn select_block_type(line: &str) -> Result<BlockType, ParseError> {
+pub(super) fn select_block_type(line: &str) -> Result<BlockType, ParseError> {
     match line {
         "info {" => Ok(BlockType::Info),
         "programstatus {" => Ok(BlockType::Program),
         "hoststatus {" => Ok(BlockType::Host),
         "servicestatus {" => Ok(BlockType::Service),
         "contactstatus {" => Ok(BlockType::Contact),
+        "hostcomment {" => Ok(BlockType::HostComment),
+        "servicecomment {" => Ok(BlockType::ServiceComment),
+        "hostdowntime {" => Ok(BlockType::HostDowntime),
+        "servicedowntime {" => Ok(BlockType::ServiceDowntime),
         _ => Err(ParseError::UnexpectedLine(line.to_string())),
     }
 }
@@ -128,11 +134,12 @@ impl<'a> Iterator for Lines<'a> {
             return Some(Err(ParseError::UnexpectedEndOfLine));
         }
 
-        return None;
+        None
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
     use super::*;
 
@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+use std::str::Split;
+
+use super::block::{select_block_type, ParseError};
+use super::stream::{StatusObject, StreamError};
+
+/// Reads status.dat from a buffer holding the whole file. Keys and values
+/// are borrowed from the buffer, and one map is reused for every block, so
+/// the only copies made are the strings of the objects returned.
+pub(super) struct BufferObjects<'a> {
+    lines: Split<'a, char>,
+    key_values: HashMap<&'a str, &'a str>,
+}
+
+impl<'a> BufferObjects<'a> {
+    pub(super) fn new(text: &'a str) -> BufferObjects<'a> {
+        BufferObjects {
+            lines: text.split('\n'),
+            key_values: HashMap::new(),
+        }
+    }
+
+    /// The next non-empty line that is not a comment, trimmed.
+    fn next_line(&mut self) -> Option<&'a str> {
+        self.lines
+            .by_ref()
+            .map(str::trim)
+            .find(|line| !line.is_empty() && !line.starts_with('#'))
+    }
+
+    fn next_object(&mut self) -> Option<Result<Option<StatusObject>, StreamError>> {
+        let block_type = match select_block_type(self.next_line()?) {
+            Ok(block_type) => block_type,
+            Err(error) => return Some(Err(error.into())),
+        };
+
+        self.key_values.clear();
+        loop {
+            let line = match self.next_line() {
+                Some("}") => break,
+                Some(line) => line,
+                None => return Some(Err(ParseError::UnexpectedEndOfLine.into())),
+            };
+            match line.split_once('=') {
+                Some((key, value)) => {
+                    self.key_values.insert(key, value);
+                }
+                None => {
+                    return Some(Err(ParseError::InvalidKeyValue(line.to_string()).into()));
+                }
+            }
+        }
+
+        Some(StatusObject::from_key_values(&block_type, &self.key_values).map_err(Into::into))
+    }
+}
+
+impl Iterator for BufferObjects<'_> {
+    type Item = Result<StatusObject, StreamError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.next_object()? {
+                Ok(Some(object)) => return Some(Ok(object)),
+                Ok(None) => continue,
+                Err(error) => return Some(Err(error)),
+            }
+        }
+    }
+}
+
+/// Inputs are split into pieces of at least this many bytes, so small files
+/// are not spread over threads.
+#[cfg(feature = "parallel")]
+const MIN_CHUNK_LEN: usize = 256 * 1024;
+
+/// Converts the objects of `text` on the rayon thread pool, one piece of
+/// whole blocks per task. Objects and the first error keep file order.
+#[cfg(feature = "parallel")]
+pub(super) fn par_objects(text: &str) -> Result<Vec<StatusObject>, StreamError> {
+    use rayon::prelude::*;
+
+    let chunk_len = MIN_CHUNK_LEN.max(text.len() / rayon::current_num_threads());
+    let chunks = split_blocks(text, chunk_len)
+        .par_iter()
+        .map(|chunk| BufferObjects::new(chunk).collect::<Result<Vec<_>, _>>())
+        .collect::<Vec<_>>();
+    let mut objects = Vec::new();
+    for chunk in chunks {
+        objects.extend(chunk?);
+    }
+    Ok(objects)
+}
+
+/// Splits `text` into pieces of about `chunk_len` bytes, each ending after
+/// the `}` line closing a block.
+#[cfg(feature = "parallel")]
+fn split_blocks(text: &str, chunk_len: usize) -> Vec<&str> {
+    let mut chunks = Vec::new();
+    let mut rest = text;
+    while rest.len() > chunk_len {
+        match block_end(rest, chunk_len) {
+            Some(end) => {
+                let (chunk, tail) = rest.split_at(end);
+                chunks.push(chunk);
+                rest = tail;
+            }
+            None => break,
+        }
+    }
+    chunks.push(rest);
+    chunks
+}
+
+/// The end of the first `}` line after the line containing byte `from`.
+#[cfg(feature = "parallel")]
+fn block_end(text: &str, from: usize) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let mut start = from + bytes[from..].iter().position(|&b| b == b'\n')? + 1;
+    while start < bytes.len() {
+        let end = bytes[start..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or(bytes.len(), |i| start + i + 1);
+        if text[start..end].trim() == "}" {
+            return Some(end);
+        }
+        start = end;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nagios::stream::StatusObjects;
+
+    #[test]
+    fn test_same_as_stream() {
+        let text = std::fs::read_to_string("testdata/status.dat").unwrap();
+        let objects = BufferObjects::new(&text)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let streamed = StatusObjects::new(text.as_bytes())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(objects.len(), 14);
+        assert_eq!(objects, streamed);
+    }
+
+    #[test]
+    fn test_errors() {
+        struct TestCase<'a>(&'a str, &'a str);
+        let test_cases = vec![
+            TestCase(
+                "unexpected_block {\ncreated=1\n}\n",
+                "unexpected line: unexpected_block {",
+            ),
+            TestCase(
+                "hoststatus {\n  created=1\n  error_line\n}\n",
+                "invalid key value: error_line",
+            ),
+            TestCase("hoststatus {\n  created=1\n", "unexpected end of line"),
+            TestCase(
+                "hoststatus {\nhost_name=localhost\n}\n",
+                "key does not exists: modified_attributes",
+            ),
+        ];
+        for test_case in test_cases {
+            let error = BufferObjects::new(test_case.0)
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap_err();
+            assert_eq!(error.to_string(), test_case.1, "{}", test_case.0);
+        }
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_split_blocks() {
+        let text = "info {\nversion=4.4.6\n}\nhoststatus {\nhost_name=a\n}\nhoststatus {\nhost_name=b\n  }\n";
+        struct TestCase<'a>(usize, Vec<&'a str>);
+        let test_cases = vec![
+            TestCase(1000, vec![text]),
+            TestCase(
+                1,
+                vec![
+                    "info {\nversion=4.4.6\n}\n",
+                    "hoststatus {\nhost_name=a\n}\n",
+                    "hoststatus {\nhost_name=b\n  }\n",
+                    "",
+                ],
+            ),
+            TestCase(
+                25,
+                vec![
+                    "info {\nversion=4.4.6\n}\nhoststatus {\nhost_name=a\n}\n",
+                    "hoststatus {\nhost_name=b\n  }\n",
+                ],
+            ),
+        ];
+        for test_case in test_cases {
+            let chunks = split_blocks(text, test_case.0);
+            assert_eq!(chunks, test_case.1, "{}", test_case.0);
+            assert_eq!(chunks.concat(), text);
+        }
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_par_objects() {
+        let status = std::fs::read_to_string("testdata/status.dat").unwrap();
+        let text = (0..100)
+            .map(|i| status.replace("localhost", &format!("host{:03}", i)))
+            .collect::<String>();
+        assert!(text.len() > MIN_CHUNK_LEN);
+        let objects = par_objects(&text).unwrap();
+        let sequential = BufferObjects::new(&text)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(objects, sequential);
+
+        let broken = format!("{}hoststatus {{\nbroken\n}}\n{}", text, text);
+        assert_eq!(
+            par_objects(&broken).unwrap_err().to_string(),
+            "invalid key value: broken"
+        );
+    }
+}
@@ -0,0 +1,307 @@
+use anyhow::{anyhow, Result};
+use std::fs::{self, File, Metadata};
+use std::io::Read;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::{Duration, Instant};
+
+use super::NagiosStatus;
+
+/// Identifies a version of status.dat. Nagios replaces the file on every
+/// update, so the inode changes even when mtime and size do not.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct FileVersion {
+    inode: u64,
+    mtime: i64,
+    mtime_nsec: i64,
+    size: u64,
+}
+
+impl From<&Metadata> for FileVersion {
+    fn from(metadata: &Metadata) -> Self {
+        FileVersion {
+            inode: metadata.ino(),
+            mtime: metadata.mtime(),
+            mtime_nsec: metadata.mtime_nsec(),
+            size: metadata.size(),
+        }
+    }
+}
+
+/// The outcome of the reads so far.
+#[derive(Debug, Default)]
+struct State {
+    /// The last good read: the version read, the status and when.
+    good: Option<(FileVersion, Arc<NagiosStatus>, Instant)>,
+    /// The error of the latest read, if it failed, and the version it failed
+    /// on if the file could be opened.
+    failed: Option<(Option<FileVersion>, String)>,
+}
+
+/// The parsed status.dat, re-read only when the file changes.
+///
+/// Nagios writes a temporary file and renames it over status.dat, so the
+/// file is opened once and its version taken from the open file: what is
+/// read is always one complete update. If re-reading fails, the last good
+/// status is kept, and a version that failed to parse is not read again.
+#[derive(Debug)]
+pub struct StatusCache {
+    path: PathBuf,
+    state: RwLock<State>,
+    reload: Mutex<()>,
+}
+
+impl StatusCache {
+    pub fn new<P: AsRef<Path>>(path: P) -> StatusCache {
+        StatusCache {
+            path: path.as_ref().to_path_buf(),
+            state: RwLock::new(State::default()),
+            reload: Mutex::new(()),
+        }
+    }
+
+    /// The status, re-read first if the file changed. While another thread
+    /// re-reads it, the last good status is returned without waiting.
+    pub fn get(&self) -> Result<Arc<NagiosStatus>> {
+        let version = match fs::metadata(&self.path) {
+            Ok(metadata) => FileVersion::from(&metadata),
+            Err(error) => {
+                self.fail(None, error.to_string());
+                return self.last().ok_or(error.into());
+            }
+        };
+        if let Some(result) = self.cached(version) {
+            return result;
+        }
+
+        let _reload = match self.reload.try_lock() {
+            Ok(guard) => guard,
+            Err(_) => match self.last() {
+                Some(status) => return Ok(status),
+                None => self
+                    .reload
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner()),
+            },
+        };
+        match self.read() {
+            Ok(status) => Ok(status),
+            Err(error) => self.last().ok_or(error),
+        }
+    }
+
+    /// The last status read, without looking at the file.
+    pub fn last(&self) -> Option<Arc<NagiosStatus>> {
+        self.state()
+            .good
+            .as_ref()
+            .map(|(_, status, _)| status.clone())
+    }
+
+    /// Why the latest read failed, `None` if it succeeded. While this is set,
+    /// `get` returns a status older than the file.
+    pub fn last_error(&self) -> Option<String> {
+        self.state().failed.as_ref().map(|(_, error)| error.clone())
+    }
+
+    /// Time since the status returned by `last` was read.
+    pub fn age(&self) -> Option<Duration> {
+        self.state()
+            .good
+            .as_ref()
+            .map(|(_, _, read_at)| read_at.elapsed())
+    }
+
+    fn state(&self) -> RwLockReadGuard<'_, State> {
+        self.state
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn state_mut(&self) -> RwLockWriteGuard<'_, State> {
+        self.state
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// The result of an earlier read of `version`, if there was one.
+    fn cached(&self, version: FileVersion) -> Option<Result<Arc<NagiosStatus>>> {
+        let state = self.state();
+        match (&state.good, &state.failed) {
+            (Some((good_version, status, _)), _) if *good_version == version => {
+                Some(Ok(status.clone()))
+            }
+            (good, Some((Some(failed_version), error))) if *failed_version == version => {
+                Some(match good {
+                    Some((_, status, _)) => Ok(status.clone()),
+                    None => Err(anyhow!("{}", error)),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn fail(&self, version: Option<FileVersion>, error: String) {
+        self.state_mut().failed = Some((version, error));
+    }
+
+    /// Reads the file unless another thread already read this version.
+    fn read(&self) -> Result<Arc<NagiosStatus>> {
+        let mut file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(error) => {
+                self.fail(None, error.to_string());
+                return Err(error.into());
+            }
+        };
+        let version = FileVersion::from(&file.metadata()?);
+        if let Some(result) = self.cached(version) {
+            return result;
+        }
+
+        let mut bytes = Vec::new();
+        if let Err(error) = file.read_to_end(&mut bytes) {
+            self.fail(None, error.to_string());
+            return Err(error.into());
+        }
+        match NagiosStatus::parse_bytes(&bytes) {
+            Ok(status) => {
+                let status = Arc::new(status);
+                let mut state = self.state_mut();
+                state.good = Some((version, status.clone(), Instant::now()));
+                state.failed = None;
+                Ok(status)
+            }
+            Err(error) => {
+                self.fail(Some(version), error.to_string());
+                Err(error)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::OpenOptions;
+    use std::io::Write;
+    use std::thread;
+
+    fn cache(dir: &Path) -> StatusCache {
+        let path = dir.join("status.dat");
+        fs::copy("testdata/status.dat", &path).unwrap();
+        StatusCache::new(path)
+    }
+
+    /// Replaces status.dat the way Nagios does.
+    fn update(dir: &Path, contents: &str) {
+        let tmp_path = dir.join("status.dat.tmp");
+        fs::write(&tmp_path, contents).unwrap();
+        fs::rename(&tmp_path, dir.join("status.dat")).unwrap();
+    }
+
+    fn nagios_pid(status: &NagiosStatus) -> &str {
+        &status.get_program()["nagios_pid"]
+    }
+
+    #[test]
+    fn test_get() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = cache(dir.path());
+        assert!(cache.last().is_none());
+        let first = cache.get().unwrap();
+        assert!(Arc::ptr_eq(&first, &cache.get().unwrap()));
+
+        // the same contents under a new inode are read again
+        update(
+            dir.path(),
+            &fs::read_to_string("testdata/status.dat").unwrap(),
+        );
+        let second = cache.get().unwrap();
+        assert!(!Arc::ptr_eq(&first, &second));
+
+        let updated = fs::read_to_string("testdata/status.dat")
+            .unwrap()
+            .replace("nagios_pid=23", "nagios_pid=42");
+        update(dir.path(), &updated);
+        assert_eq!(nagios_pid(&cache.get().unwrap()), "42");
+        assert_eq!(nagios_pid(&cache.last().unwrap()), "42");
+    }
+
+    #[test]
+    fn test_keep_last_good() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = cache(dir.path());
+        let first = cache.get().unwrap();
+
+        assert!(cache.last_error().is_none());
+
+        update(dir.path(), "hoststatus {\nbroken\n}\n");
+        assert!(Arc::ptr_eq(&first, &cache.get().unwrap()));
+        assert_eq!(
+            cache.last_error().as_deref(),
+            Some("invalid key value: broken")
+        );
+
+        fs::remove_file(dir.path().join("status.dat")).unwrap();
+        assert!(Arc::ptr_eq(&first, &cache.get().unwrap()));
+        assert!(cache.last_error().unwrap().contains("No such file"));
+
+        // a good update clears the error
+        update(
+            dir.path(),
+            &fs::read_to_string("testdata/status.dat").unwrap(),
+        );
+        assert!(!Arc::ptr_eq(&first, &cache.get().unwrap()));
+        assert!(cache.last_error().is_none());
+    }
+
+    #[test]
+    fn test_skip_failed_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("status.dat");
+        let cache = cache(dir.path());
+        let first = cache.get().unwrap();
+        thread::sleep(Duration::from_millis(10));
+        assert!(cache.age().unwrap() >= Duration::from_millis(10));
+
+        let broken = "hoststatus {\nbroken\n}\n";
+        update(dir.path(), broken);
+        assert!(Arc::ptr_eq(&first, &cache.get().unwrap()));
+
+        // a valid file of the same version is not read
+        let modified = fs::metadata(&path).unwrap().modified().unwrap();
+        let valid = format!("#{}\n", "x".repeat(broken.len() - 2));
+        let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.write_all(valid.as_bytes()).unwrap();
+        file.set_modified(modified).unwrap();
+        drop(file);
+        assert!(Arc::ptr_eq(&first, &cache.get().unwrap()));
+        assert_eq!(
+            cache.last_error().as_deref(),
+            Some("invalid key value: broken")
+        );
+        assert!(cache.age().unwrap() >= Duration::from_millis(10));
+
+        // it is once the version changes
+        let stale_age = cache.age().unwrap();
+        update(dir.path(), &valid);
+        assert_eq!(cache.get().unwrap().hosts().count(), 0);
+        assert!(cache.last_error().is_none());
+        assert!(cache.age().unwrap() < stale_age);
+    }
+
+    #[test]
+    fn test_get_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = StatusCache::new(dir.path().join("status.dat"));
+        assert!(cache.get().is_err());
+
+        update(dir.path(), "hoststatus {\nbroken\n}\n");
+        assert_eq!(
+            cache.get().unwrap_err().to_string(),
+            "invalid key value: broken"
+        );
+    }
+}
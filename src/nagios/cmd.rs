@@ -1,23 +1,55 @@
 use nagrs_derive::NagiosCmd;
-use std::io::BufWriter;
 use std::io::Write;
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum ParseCmdError {
+    #[error("empty command")]
+    Empty,
+    #[error("invalid command name: {0}")]
+    InvalidName(String),
+    #[error("invalid timestamp: {0}")]
+    InvalidTimestamp(String),
+}
 
 pub trait NagiosCmd {
-    fn to_cmd_string(&self) -> String;
+    /// Command name, e.g. `DISABLE_HOST_CHECK`.
+    fn name(&self) -> &str;
+
+    /// Arguments in command order, paired with their field names.
+    fn args(&self) -> Vec<(&str, String)>;
+
+    fn to_cmd_string(&self) -> String {
+        let mut command_string = self.name().to_string();
+        for (_, value) in self.args() {
+            command_string.push(';');
+            command_string.push_str(&value);
+        }
+        command_string
+    }
+
+    /// Value of the argument named `key`, e.g. `host_name`.
+    fn arg(&self, key: &str) -> Option<String> {
+        self.args()
+            .into_iter()
+            .find(|(name, _)| *name == key)
+            .map(|(_, value)| value)
+    }
 }
 
 pub fn write_cmd_line<W: Write>(
-    cmd: &Box<dyn NagiosCmd>,
+    cmd: &dyn NagiosCmd,
     timestamp: i64,
-    writer: &mut BufWriter<W>,
+    writer: &mut W,
 ) -> std::io::Result<()> {
     let cmd_str = cmd.to_cmd_string();
-    writer.write(format!("[{}] {}\n", timestamp, cmd_str).as_bytes())?;
+    writer.write_all(format!("[{}] {}\n", timestamp, cmd_str).as_bytes())?;
     Ok(())
 }
 
 //////////////////////////////////
-/// Cmd implementation
+// Cmd implementation
 
 //////////////////////////////////
 /// ENABLE_HOSTGROUP_HOST_CHECKS
@@ -135,6 +167,229 @@ pub struct DisableHostSvcNotifications {
     pub host_name: String,
 }
 
+//////////////////////////////////
+/// ENABLE_HOST_EVENT_HANDLER
+
+#[derive(Debug, NagiosCmd)]
+pub struct EnableHostEventHandler {
+    pub host_name: String,
+}
+
+//////////////////////////////////
+/// DISABLE_HOST_EVENT_HANDLER
+
+#[derive(Debug, NagiosCmd)]
+pub struct DisableHostEventHandler {
+    pub host_name: String,
+}
+
+//////////////////////////////////
+/// ENABLE_HOST_FLAP_DETECTION
+
+#[derive(Debug, NagiosCmd)]
+pub struct EnableHostFlapDetection {
+    pub host_name: String,
+}
+
+//////////////////////////////////
+/// DISABLE_HOST_FLAP_DETECTION
+
+#[derive(Debug, NagiosCmd)]
+pub struct DisableHostFlapDetection {
+    pub host_name: String,
+}
+
+//////////////////////////////////
+/// ENABLE_SVC_EVENT_HANDLER
+
+#[derive(Debug, NagiosCmd)]
+pub struct EnableSvcEventHandler {
+    pub host_name: String,
+    pub service_description: String,
+}
+
+//////////////////////////////////
+/// DISABLE_SVC_EVENT_HANDLER
+
+#[derive(Debug, NagiosCmd)]
+pub struct DisableSvcEventHandler {
+    pub host_name: String,
+    pub service_description: String,
+}
+
+//////////////////////////////////
+/// ENABLE_SVC_FLAP_DETECTION
+
+#[derive(Debug, NagiosCmd)]
+pub struct EnableSvcFlapDetection {
+    pub host_name: String,
+    pub service_description: String,
+}
+
+//////////////////////////////////
+/// DISABLE_SVC_FLAP_DETECTION
+
+#[derive(Debug, NagiosCmd)]
+pub struct DisableSvcFlapDetection {
+    pub host_name: String,
+    pub service_description: String,
+}
+
+//////////////////////////////////
+/// CHANGE_HOST_MODATTR
+
+#[derive(Debug, NagiosCmd)]
+pub struct ChangeHostModattr {
+    pub host_name: String,
+    pub value: u32,
+}
+
+//////////////////////////////////
+/// CHANGE_SVC_MODATTR
+
+#[derive(Debug, NagiosCmd)]
+pub struct ChangeSvcModattr {
+    pub host_name: String,
+    pub service_description: String,
+    pub value: u32,
+}
+
+//////////////////////////////////
+// Raw command
+
+/// A command given by name and positional arguments, e.g. read from another
+/// program. Argument names are inferred from the command name (`host_name`,
+/// `service_description`, `hostgroup_name`, ...) so that policies and undo
+/// can inspect them; other arguments are named `arg1`, `arg2`, ...
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawCmd {
+    name: String,
+    args: Vec<(String, String)>,
+}
+
+impl RawCmd {
+    pub fn new(name: &str, args: Vec<String>) -> RawCmd {
+        let names = infer_arg_names(name);
+        RawCmd {
+            name: name.to_string(),
+            args: args
+                .into_iter()
+                .enumerate()
+                .map(|(i, value)| {
+                    let key = names
+                        .get(i)
+                        .map_or_else(|| format!("arg{}", i + 1), |key| key.to_string());
+                    (key, value)
+                })
+                .collect(),
+        }
+    }
+}
+
+fn infer_arg_names(name: &str) -> Vec<&'static str> {
+    // commands whose arguments do not follow from the words of their name
+    match name {
+        "DEL_HOST_COMMENT" | "DEL_SVC_COMMENT" => return vec!["comment_id"],
+        "DEL_HOST_DOWNTIME" | "DEL_SVC_DOWNTIME" => return vec!["downtime_id"],
+        "DEL_DOWNTIME_BY_HOST_NAME" => {
+            return vec!["host_name", "service_description", "start_time", "comment"]
+        }
+        "DEL_DOWNTIME_BY_HOSTGROUP_NAME" => {
+            return vec![
+                "hostgroup_name",
+                "host_name",
+                "service_description",
+                "start_time",
+                "comment",
+            ]
+        }
+        "DEL_DOWNTIME_BY_START_TIME_COMMENT" => return vec!["start_time", "comment"],
+        // program-wide commands that name hosts or services without taking one
+        "START_EXECUTING_HOST_CHECKS"
+        | "STOP_EXECUTING_HOST_CHECKS"
+        | "START_EXECUTING_SVC_CHECKS"
+        | "STOP_EXECUTING_SVC_CHECKS"
+        | "START_ACCEPTING_PASSIVE_HOST_CHECKS"
+        | "STOP_ACCEPTING_PASSIVE_HOST_CHECKS"
+        | "START_ACCEPTING_PASSIVE_SVC_CHECKS"
+        | "STOP_ACCEPTING_PASSIVE_SVC_CHECKS"
+        | "START_OBSESSING_OVER_HOST_CHECKS"
+        | "STOP_OBSESSING_OVER_HOST_CHECKS"
+        | "START_OBSESSING_OVER_SVC_CHECKS"
+        | "STOP_OBSESSING_OVER_SVC_CHECKS"
+        | "ENABLE_HOST_FRESHNESS_CHECKS"
+        | "DISABLE_HOST_FRESHNESS_CHECKS"
+        | "ENABLE_SERVICE_FRESHNESS_CHECKS"
+        | "DISABLE_SERVICE_FRESHNESS_CHECKS" => return vec![],
+        _ if name.contains("GLOBAL") => return vec![],
+        _ => {}
+    }
+
+    let has = |word: &str| name.split('_').any(|part| part == word);
+    if has("HOSTGROUP") {
+        vec!["hostgroup_name"]
+    } else if has("SERVICEGROUP") {
+        vec!["servicegroup_name"]
+    } else if has("CONTACTGROUP") {
+        vec!["contactgroup_name"]
+    } else if has("CONTACT") {
+        vec!["contact_name"]
+    } else if has("HOST") {
+        vec!["host_name"]
+    } else if has("SVC") || has("SERVICE") {
+        vec!["host_name", "service_description"]
+    } else {
+        vec![]
+    }
+}
+
+impl FromStr for RawCmd {
+    type Err = ParseCmdError;
+
+    /// Parses `NAME;arg1;arg2`, optionally preceded by the `[timestamp] ` of a
+    /// command file line. The timestamp is discarded.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut s = s.trim();
+        if let Some(rest) = s.strip_prefix('[') {
+            let (timestamp, rest) = rest
+                .split_once(']')
+                .ok_or_else(|| ParseCmdError::InvalidTimestamp(s.to_string()))?;
+            timestamp
+                .parse::<i64>()
+                .map_err(|_| ParseCmdError::InvalidTimestamp(timestamp.to_string()))?;
+            s = rest.trim_start();
+        }
+        let mut parts = s.split(';');
+        let name = parts.next().unwrap_or_default();
+        if name.is_empty() {
+            return Err(ParseCmdError::Empty);
+        }
+        if !name
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+        {
+            return Err(ParseCmdError::InvalidName(name.to_string()));
+        }
+        Ok(RawCmd::new(
+            name,
+            parts.map(|arg| arg.to_string()).collect(),
+        ))
+    }
+}
+
+impl NagiosCmd for RawCmd {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn args(&self) -> Vec<(&str, String)> {
+        self.args
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.clone()))
+            .collect()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use chrono::DateTime;
@@ -150,7 +405,7 @@ mod tests {
             .unwrap()
             .with_timezone(&Utc);
         let mut buf = BufWriter::new(vec![]);
-        let result = write_cmd_line(&cmd, datetime.timestamp(), &mut buf);
+        let result = write_cmd_line(cmd.as_ref(), datetime.timestamp(), &mut buf);
 
         match result {
             Err(_) => "".to_string(),
@@ -268,10 +523,230 @@ mod tests {
                 }),
                 expected: "[1647824400] DISABLE_HOST_SVC_NOTIFICATIONS;localhost\n",
             },
+            // ENABLE_HOST_EVENT_HANDLER
+            TestCase {
+                cmd: Box::new(EnableHostEventHandler {
+                    host_name: "localhost".to_string(),
+                }),
+                expected: "[1647824400] ENABLE_HOST_EVENT_HANDLER;localhost\n",
+            },
+            // DISABLE_HOST_EVENT_HANDLER
+            TestCase {
+                cmd: Box::new(DisableHostEventHandler {
+                    host_name: "localhost".to_string(),
+                }),
+                expected: "[1647824400] DISABLE_HOST_EVENT_HANDLER;localhost\n",
+            },
+            // ENABLE_HOST_FLAP_DETECTION
+            TestCase {
+                cmd: Box::new(EnableHostFlapDetection {
+                    host_name: "localhost".to_string(),
+                }),
+                expected: "[1647824400] ENABLE_HOST_FLAP_DETECTION;localhost\n",
+            },
+            // DISABLE_HOST_FLAP_DETECTION
+            TestCase {
+                cmd: Box::new(DisableHostFlapDetection {
+                    host_name: "localhost".to_string(),
+                }),
+                expected: "[1647824400] DISABLE_HOST_FLAP_DETECTION;localhost\n",
+            },
+            // ENABLE_SVC_EVENT_HANDLER
+            TestCase {
+                cmd: Box::new(EnableSvcEventHandler {
+                    host_name: "localhost".to_string(),
+                    service_description: "Current Load".to_string(),
+                }),
+                expected: "[1647824400] ENABLE_SVC_EVENT_HANDLER;localhost;Current Load\n",
+            },
+            // DISABLE_SVC_EVENT_HANDLER
+            TestCase {
+                cmd: Box::new(DisableSvcEventHandler {
+                    host_name: "localhost".to_string(),
+                    service_description: "Current Load".to_string(),
+                }),
+                expected: "[1647824400] DISABLE_SVC_EVENT_HANDLER;localhost;Current Load\n",
+            },
+            // ENABLE_SVC_FLAP_DETECTION
+            TestCase {
+                cmd: Box::new(EnableSvcFlapDetection {
+                    host_name: "localhost".to_string(),
+                    service_description: "Current Load".to_string(),
+                }),
+                expected: "[1647824400] ENABLE_SVC_FLAP_DETECTION;localhost;Current Load\n",
+            },
+            // DISABLE_SVC_FLAP_DETECTION
+            TestCase {
+                cmd: Box::new(DisableSvcFlapDetection {
+                    host_name: "localhost".to_string(),
+                    service_description: "Current Load".to_string(),
+                }),
+                expected: "[1647824400] DISABLE_SVC_FLAP_DETECTION;localhost;Current Load\n",
+            },
+            // CHANGE_HOST_MODATTR
+            TestCase {
+                cmd: Box::new(ChangeHostModattr {
+                    host_name: "localhost".to_string(),
+                    value: 0,
+                }),
+                expected: "[1647824400] CHANGE_HOST_MODATTR;localhost;0\n",
+            },
+            // CHANGE_SVC_MODATTR
+            TestCase {
+                cmd: Box::new(ChangeSvcModattr {
+                    host_name: "localhost".to_string(),
+                    service_description: "Current Load".to_string(),
+                    value: 0,
+                }),
+                expected: "[1647824400] CHANGE_SVC_MODATTR;localhost;Current Load;0\n",
+            },
         ];
 
         for test_case in test_cases {
             assert_eq!(written_string(test_case.cmd), test_case.expected);
         }
     }
+
+    #[test]
+    fn test_args() {
+        let cmd = EnableSvcCheck {
+            host_name: "localhost".to_string(),
+            service_description: "Current Load".to_string(),
+        };
+        assert_eq!(cmd.name(), "ENABLE_SVC_CHECK");
+        assert_eq!(
+            cmd.args(),
+            vec![
+                ("host_name", "localhost".to_string()),
+                ("service_description", "Current Load".to_string()),
+            ]
+        );
+        assert_eq!(cmd.arg("host_name"), Some("localhost".to_string()));
+        assert_eq!(cmd.arg("hostgroup_name"), None);
+    }
+
+    #[test]
+    fn test_raw_cmd() {
+        struct TestCase<'a>(&'a str, Result<Vec<(&'a str, &'a str)>, ParseCmdError>);
+        let test_cases = vec![
+            TestCase("ENABLE_NOTIFICATIONS", Ok(vec![])),
+            TestCase(
+                "[1647824400] DISABLE_HOST_CHECK;web01",
+                Ok(vec![("host_name", "web01")]),
+            ),
+            TestCase(
+                "SCHEDULE_SVC_DOWNTIME;web01;HTTP;1647824400;1647831600;1;0;7200;admin;upgrade",
+                Ok(vec![
+                    ("host_name", "web01"),
+                    ("service_description", "HTTP"),
+                    ("arg3", "1647824400"),
+                    ("arg4", "1647831600"),
+                    ("arg5", "1"),
+                    ("arg6", "0"),
+                    ("arg7", "7200"),
+                    ("arg8", "admin"),
+                    ("arg9", "upgrade"),
+                ]),
+            ),
+            TestCase(
+                "PROCESS_SERVICE_CHECK_RESULT;web01;HTTP;0;OK",
+                Ok(vec![
+                    ("host_name", "web01"),
+                    ("service_description", "HTTP"),
+                    ("arg3", "0"),
+                    ("arg4", "OK"),
+                ]),
+            ),
+            TestCase(
+                "DISABLE_HOST_SVC_CHECKS;web01",
+                Ok(vec![("host_name", "web01")]),
+            ),
+            TestCase(
+                "ENABLE_HOSTGROUP_HOST_CHECKS;linux-servers",
+                Ok(vec![("hostgroup_name", "linux-servers")]),
+            ),
+            TestCase("DEL_HOST_COMMENT;3", Ok(vec![("comment_id", "3")])),
+            TestCase("DEL_SVC_COMMENT;4", Ok(vec![("comment_id", "4")])),
+            TestCase("DEL_HOST_DOWNTIME;11", Ok(vec![("downtime_id", "11")])),
+            TestCase("DEL_SVC_DOWNTIME;12", Ok(vec![("downtime_id", "12")])),
+            TestCase(
+                "DEL_ALL_HOST_COMMENTS;web01",
+                Ok(vec![("host_name", "web01")]),
+            ),
+            TestCase(
+                "DEL_ALL_SVC_COMMENTS;web01;HTTP",
+                Ok(vec![
+                    ("host_name", "web01"),
+                    ("service_description", "HTTP"),
+                ]),
+            ),
+            TestCase(
+                "DEL_DOWNTIME_BY_HOST_NAME;web01",
+                Ok(vec![("host_name", "web01")]),
+            ),
+            TestCase(
+                "DEL_DOWNTIME_BY_HOST_NAME;web01;HTTP;1647824400;upgrade",
+                Ok(vec![
+                    ("host_name", "web01"),
+                    ("service_description", "HTTP"),
+                    ("start_time", "1647824400"),
+                    ("comment", "upgrade"),
+                ]),
+            ),
+            TestCase(
+                "DEL_DOWNTIME_BY_HOSTGROUP_NAME;grp",
+                Ok(vec![("hostgroup_name", "grp")]),
+            ),
+            TestCase(
+                "DEL_DOWNTIME_BY_HOSTGROUP_NAME;grp;web01",
+                Ok(vec![("hostgroup_name", "grp"), ("host_name", "web01")]),
+            ),
+            TestCase(
+                "DEL_DOWNTIME_BY_START_TIME_COMMENT;1647824400;upgrade",
+                Ok(vec![("start_time", "1647824400"), ("comment", "upgrade")]),
+            ),
+            TestCase(
+                "CHANGE_GLOBAL_HOST_EVENT_HANDLER;handle-host",
+                Ok(vec![("arg1", "handle-host")]),
+            ),
+            TestCase(
+                "CHANGE_GLOBAL_SVC_EVENT_HANDLER;handle-svc",
+                Ok(vec![("arg1", "handle-svc")]),
+            ),
+            TestCase("START_EXECUTING_HOST_CHECKS", Ok(vec![])),
+            TestCase("STOP_EXECUTING_SVC_CHECKS", Ok(vec![])),
+            TestCase("START_ACCEPTING_PASSIVE_HOST_CHECKS", Ok(vec![])),
+            TestCase("STOP_ACCEPTING_PASSIVE_SVC_CHECKS", Ok(vec![])),
+            TestCase("START_OBSESSING_OVER_HOST_CHECKS", Ok(vec![])),
+            TestCase("STOP_OBSESSING_OVER_SVC_CHECKS", Ok(vec![])),
+            TestCase("ENABLE_HOST_FRESHNESS_CHECKS", Ok(vec![])),
+            TestCase("DISABLE_SERVICE_FRESHNESS_CHECKS", Ok(vec![])),
+            TestCase("", Err(ParseCmdError::Empty)),
+            TestCase(
+                "[now] ENABLE_NOTIFICATIONS",
+                Err(ParseCmdError::InvalidTimestamp("now".to_string())),
+            ),
+            TestCase(
+                "enable_notifications",
+                Err(ParseCmdError::InvalidName(
+                    "enable_notifications".to_string(),
+                )),
+            ),
+        ];
+        for test_case in test_cases {
+            let cmd = test_case.0.parse::<RawCmd>();
+            assert_eq!(
+                cmd.as_ref().map(|cmd| cmd.args()),
+                test_case.1.as_ref().map(|args| args
+                    .iter()
+                    .map(|(key, value)| (*key, value.to_string()))
+                    .collect::<Vec<_>>()),
+                "{}",
+                test_case.0
+            );
+        }
+
+        let cmd: RawCmd = "[1647824400] DISABLE_SVC_CHECK;web01;HTTP".parse().unwrap();
+        assert_eq!(cmd.to_cmd_string(), "DISABLE_SVC_CHECK;web01;HTTP");
+    }
 }
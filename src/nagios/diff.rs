@@ -0,0 +1,333 @@
+use serde::de::{self, DeserializeOwned, Deserializer, Visitor};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::fmt;
+use thiserror::Error;
+
+use super::object::{Host, Service};
+use super::{Matched, NagiosStatus};
+
+#[derive(Error, Debug, PartialEq)]
+pub enum DiffError {
+    #[error("unknown host or service field: {0}")]
+    UnknownField(String),
+}
+
+/// Fields that change on every check, ignored by `DiffOptions::ignore_volatile`.
+pub const VOLATILE_FIELDS: &[&str] = &[
+    "last_check",
+    "next_check",
+    "check_latency",
+    "check_execution_time",
+    "last_update",
+];
+
+#[derive(Debug, Clone, Default)]
+pub struct DiffOptions {
+    ignored: HashSet<String>,
+}
+
+impl DiffOptions {
+    pub fn new() -> DiffOptions {
+        DiffOptions::default()
+    }
+
+    /// Leaves `field` of hosts and services out of the diff. `field` must be
+    /// the name of a host or service field, as serialized.
+    pub fn ignore(mut self, field: &str) -> Result<DiffOptions, DiffError> {
+        if !field_names::<Host>().contains(&field) && !field_names::<Service>().contains(&field) {
+            return Err(DiffError::UnknownField(field.to_string()));
+        }
+        self.ignored.insert(field.to_string());
+        Ok(self)
+    }
+
+    pub fn ignore_volatile(mut self) -> DiffOptions {
+        self.ignored
+            .extend(VOLATILE_FIELDS.iter().map(|field| field.to_string()));
+        self
+    }
+}
+
+/// The field names of `T`, taken from its derived `Deserialize`, which
+/// passes them to `Deserializer::deserialize_struct`.
+fn field_names<T: DeserializeOwned>() -> &'static [&'static str] {
+    struct FieldNames<'a>(&'a mut &'static [&'static str]);
+
+    impl<'de> Deserializer<'de> for FieldNames<'_> {
+        type Error = de::value::Error;
+
+        fn deserialize_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+            Err(de::Error::custom("not a struct"))
+        }
+
+        fn deserialize_struct<V: Visitor<'de>>(
+            self,
+            _: &'static str,
+            fields: &'static [&'static str],
+            _: V,
+        ) -> Result<V::Value, Self::Error> {
+            *self.0 = fields;
+            Err(de::Error::custom("only the field names are read"))
+        }
+
+        serde::forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+            bytes byte_buf option unit unit_struct newtype_struct seq tuple
+            tuple_struct map enum identifier ignored_any
+        }
+    }
+
+    let mut fields: &'static [&'static str] = &[];
+    let _ = T::deserialize(FieldNames(&mut fields));
+    fields
+}
+
+/// A field whose value differs, as serialized to JSON.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FieldChange {
+    pub field: String,
+    pub old: Value,
+    pub new: Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum ObjectDiff {
+    Added,
+    Removed,
+    /// Sorted by field name.
+    Changed(Vec<FieldChange>),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DiffEntry {
+    pub host_name: String,
+    /// `None` for hosts.
+    pub service_description: Option<String>,
+    pub diff: ObjectDiff,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StatusDiff {
+    /// Sorted by host name, with each host before its services.
+    pub entries: Vec<DiffEntry>,
+}
+
+impl StatusDiff {
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl fmt::Display for StatusDiff {
+    /// `+ host`, `- host/service`, or the object followed by one
+    /// `field: old -> new` line per changed field.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for entry in &self.entries {
+            let name = match &entry.service_description {
+                Some(service_description) => format!("{}/{}", entry.host_name, service_description),
+                None => entry.host_name.clone(),
+            };
+            match &entry.diff {
+                ObjectDiff::Added => writeln!(f, "+ {}", name)?,
+                ObjectDiff::Removed => writeln!(f, "- {}", name)?,
+                ObjectDiff::Changed(changes) => {
+                    writeln!(f, "{}", name)?;
+                    for change in changes {
+                        writeln!(f, "    {}: {} -> {}", change.field, change.old, change.new)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn object_diff<T: Serialize>(
+    old: Option<&T>,
+    new: Option<&T>,
+    options: &DiffOptions,
+) -> Option<ObjectDiff> {
+    let (old, new) = match (old, new) {
+        (None, None) => return None,
+        (None, Some(_)) => return Some(ObjectDiff::Added),
+        (Some(_), None) => return Some(ObjectDiff::Removed),
+        (Some(old), Some(new)) => (old, new),
+    };
+    let (Ok(Value::Object(old)), Ok(Value::Object(new))) =
+        (serde_json::to_value(old), serde_json::to_value(new))
+    else {
+        unreachable!("hosts and services serialize to JSON objects");
+    };
+
+    let changes = old
+        .into_iter()
+        .filter(|(field, _)| !options.ignored.contains(field))
+        .filter_map(|(field, old)| {
+            let new = new.get(&field).cloned().unwrap_or(Value::Null);
+            (old != new).then_some(FieldChange { field, old, new })
+        })
+        .collect::<Vec<_>>();
+    if changes.is_empty() {
+        None
+    } else {
+        Some(ObjectDiff::Changed(changes))
+    }
+}
+
+impl NagiosStatus {
+    /// The host and service fields that differ from `self` to `other`.
+    pub fn diff(&self, other: &NagiosStatus) -> StatusDiff {
+        self.diff_with(other, &DiffOptions::default())
+    }
+
+    pub fn diff_with(&self, other: &NagiosStatus, options: &DiffOptions) -> StatusDiff {
+        let entries = self
+            .matched(other)
+            .filter_map(|matched| {
+                let (host_name, service_description, diff) = match matched {
+                    Matched::Host {
+                        host_name,
+                        old,
+                        new,
+                    } => (host_name, None, object_diff(old, new, options)?),
+                    Matched::Service {
+                        host_name,
+                        service_description,
+                        old,
+                        new,
+                    } => (
+                        host_name,
+                        Some(service_description.to_string()),
+                        object_diff(old, new, options)?,
+                    ),
+                };
+                Some(DiffEntry {
+                    host_name: host_name.to_string(),
+                    service_description,
+                    diff,
+                })
+            })
+            .collect();
+        StatusDiff { entries }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nagios::object::ServiceState;
+    use chrono::{DateTime, Utc};
+
+    fn status() -> NagiosStatus {
+        NagiosStatus::parse_file("testdata/status.dat").unwrap()
+    }
+
+    /// The testdata after a check of HTTP that found it critical, with
+    /// Swap Usage removed.
+    fn changed_status() -> NagiosStatus {
+        let mut status = status();
+        for service in status.services.get_mut("localhost").unwrap() {
+            if service.service_description == "HTTP" {
+                service.current_state = ServiceState::Critical;
+                service.check_latency += 1.0;
+                service.last_check = DateTime::<Utc>::from_timestamp(1700000000, 0);
+            }
+        }
+        status
+            .services
+            .get_mut("localhost")
+            .unwrap()
+            .retain(|service| service.service_description != "Swap Usage");
+        status.reindex();
+        status
+    }
+
+    #[test]
+    fn test_diff() {
+        let status = status();
+        assert!(status
+            .diff(&NagiosStatus::parse_file("testdata/status.dat").unwrap())
+            .is_empty());
+
+        let changed = changed_status();
+        struct TestCase(DiffOptions, Vec<&'static str>);
+        let test_cases = vec![
+            TestCase(
+                DiffOptions::new(),
+                vec!["check_latency", "current_state", "last_check"],
+            ),
+            TestCase(
+                DiffOptions::new().ignore("check_latency").unwrap(),
+                vec!["current_state", "last_check"],
+            ),
+            TestCase(DiffOptions::new().ignore_volatile(), vec!["current_state"]),
+        ];
+        for test_case in test_cases {
+            let diff = status.diff_with(&changed, &test_case.0);
+            assert_eq!(diff.entries.len(), 2);
+            assert_eq!(diff.entries[0].service_description.as_deref(), Some("HTTP"));
+            let ObjectDiff::Changed(changes) = &diff.entries[0].diff else {
+                panic!("HTTP is changed: {:?}", diff.entries[0].diff);
+            };
+            assert_eq!(
+                changes
+                    .iter()
+                    .map(|change| change.field.as_str())
+                    .collect::<Vec<_>>(),
+                test_case.1
+            );
+            assert_eq!(diff.entries[1].diff, ObjectDiff::Removed);
+        }
+
+        // ignoring every changed field still reports the removal
+        let diff = status.diff_with(
+            &changed,
+            &DiffOptions::new()
+                .ignore_volatile()
+                .ignore("current_state")
+                .unwrap(),
+        );
+        assert_eq!(diff.entries.len(), 1);
+        assert_eq!(changed.diff(&status).entries[1].diff, ObjectDiff::Added);
+    }
+
+    #[test]
+    fn test_ignore() {
+        struct TestCase<'a>(&'a str, Result<(), DiffError>);
+        let test_cases = vec![
+            TestCase("current_state", Ok(())),
+            TestCase("host_name", Ok(())),
+            // services only
+            TestCase("service_description", Ok(())),
+            TestCase(
+                "curent_state",
+                Err(DiffError::UnknownField("curent_state".into())),
+            ),
+            TestCase("", Err(DiffError::UnknownField("".into()))),
+        ];
+        for test_case in test_cases {
+            assert_eq!(
+                DiffOptions::new().ignore(test_case.0).map(|_| ()),
+                test_case.1,
+                "{}",
+                test_case.0
+            );
+        }
+        for field in VOLATILE_FIELDS {
+            assert!(DiffOptions::new().ignore(field).is_ok(), "{}", field);
+        }
+    }
+
+    #[test]
+    fn test_diff_display() {
+        let diff = status().diff_with(&changed_status(), &DiffOptions::new().ignore_volatile());
+        assert_eq!(
+            diff.to_string(),
+            "localhost/HTTP\n\
+             \x20   current_state: \"Ok\" -> \"Critical\"\n\
+             - localhost/Swap Usage\n"
+        );
+    }
+}
@@ -0,0 +1,206 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+
+use super::cmd::{ChangeHostModattr, ChangeSvcModattr, NagiosCmd};
+use super::object::ModifiedAttributes;
+use super::NagiosStatus;
+
+/// A host or service whose runtime state differs from its configuration.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DriftEntry {
+    pub host_name: String,
+    pub service_description: Option<String>,
+    pub modified_attributes: ModifiedAttributes,
+}
+
+impl DriftEntry {
+    /// `CHANGE_HOST_MODATTR;host;0` or `CHANGE_SVC_MODATTR;host;service;0`.
+    pub fn reset_cmd(&self) -> Box<dyn NagiosCmd> {
+        match &self.service_description {
+            Some(service_description) => Box::new(ChangeSvcModattr {
+                host_name: self.host_name.clone(),
+                service_description: service_description.clone(),
+                value: 0,
+            }),
+            None => Box::new(ChangeHostModattr {
+                host_name: self.host_name.clone(),
+                value: 0,
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DriftReport {
+    /// Sorted by host name, with each host before its services.
+    pub entries: Vec<DriftEntry>,
+}
+
+impl DriftReport {
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Entries grouped by `MODATTR_*` name. An entry with several modified
+    /// attributes appears in several groups; bits without a name are grouped
+    /// as `unknown (0x…)`.
+    pub fn by_attribute(&self) -> BTreeMap<String, Vec<&DriftEntry>> {
+        let mut groups: BTreeMap<String, Vec<&DriftEntry>> = BTreeMap::new();
+        for entry in &self.entries {
+            for name in entry.modified_attributes.names() {
+                groups.entry(name.to_string()).or_default().push(entry);
+            }
+            let unknown = entry.modified_attributes.unknown_bits();
+            if unknown != 0 {
+                groups
+                    .entry(format!("unknown ({:#x})", unknown))
+                    .or_default()
+                    .push(entry);
+            }
+        }
+        groups
+    }
+
+    pub fn reset_cmds(&self) -> Vec<Box<dyn NagiosCmd>> {
+        self.entries.iter().map(|entry| entry.reset_cmd()).collect()
+    }
+}
+
+impl fmt::Display for DriftReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, entries) in self.by_attribute() {
+            writeln!(f, "{} ({})", name, entries.len())?;
+            for entry in entries {
+                writeln!(f, "    {}", entry.reset_cmd().to_cmd_string())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl NagiosStatus {
+    /// Lists every host and service with a non-zero `modified_attributes`.
+    pub fn drift_report(&self) -> DriftReport {
+        let mut host_names: Vec<&String> = self.hosts.keys().chain(self.services.keys()).collect();
+        host_names.sort();
+        host_names.dedup();
+
+        let mut entries = Vec::new();
+        for host_name in host_names {
+            if let Some(host) = self.hosts.get(host_name) {
+                if !host.modified_attributes.is_empty() {
+                    entries.push(DriftEntry {
+                        host_name: host.host_name.clone(),
+                        service_description: None,
+                        modified_attributes: host.modified_attributes,
+                    });
+                }
+            }
+
+            let mut services: Vec<_> = self
+                .services
+                .get(host_name)
+                .into_iter()
+                .flatten()
+                .filter(|service| !service.modified_attributes.is_empty())
+                .collect();
+            services.sort_by(|a, b| a.service_description.cmp(&b.service_description));
+            entries.extend(services.into_iter().map(|service| DriftEntry {
+                host_name: service.host_name.clone(),
+                service_description: Some(service.service_description.clone()),
+                modified_attributes: service.modified_attributes,
+            }));
+        }
+
+        DriftReport { entries }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drifted_status() -> NagiosStatus {
+        let mut status = NagiosStatus::parse_file("testdata/status.dat").unwrap();
+        status
+            .hosts
+            .get_mut("localhost")
+            .unwrap()
+            .modified_attributes = ModifiedAttributes::NOTIFICATIONS_ENABLED;
+        for service in status.services.get_mut("localhost").unwrap() {
+            match service.service_description.as_str() {
+                "HTTP" => {
+                    service.modified_attributes = ModifiedAttributes::NOTIFICATIONS_ENABLED
+                        | ModifiedAttributes::ACTIVE_CHECKS_ENABLED
+                }
+                "PING" => service.modified_attributes = ModifiedAttributes::CHECK_COMMAND,
+                _ => {}
+            }
+        }
+        status
+    }
+
+    #[test]
+    fn test_drift_report() {
+        let report = drifted_status().drift_report();
+        assert_eq!(
+            report
+                .reset_cmds()
+                .iter()
+                .map(|cmd| cmd.to_cmd_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "CHANGE_HOST_MODATTR;localhost;0",
+                "CHANGE_SVC_MODATTR;localhost;HTTP;0",
+                "CHANGE_SVC_MODATTR;localhost;PING;0",
+            ]
+        );
+
+        let groups = report.by_attribute();
+        assert_eq!(
+            groups.keys().map(String::as_str).collect::<Vec<_>>(),
+            vec![
+                "MODATTR_ACTIVE_CHECKS_ENABLED",
+                "MODATTR_CHECK_COMMAND",
+                "MODATTR_NOTIFICATIONS_ENABLED",
+            ]
+        );
+        assert_eq!(groups["MODATTR_NOTIFICATIONS_ENABLED"].len(), 2);
+    }
+
+    #[test]
+    fn test_drift_report_display() {
+        assert_eq!(
+            drifted_status().drift_report().to_string(),
+            "MODATTR_ACTIVE_CHECKS_ENABLED (1)\n\
+             \x20   CHANGE_SVC_MODATTR;localhost;HTTP;0\n\
+             MODATTR_CHECK_COMMAND (1)\n\
+             \x20   CHANGE_SVC_MODATTR;localhost;PING;0\n\
+             MODATTR_NOTIFICATIONS_ENABLED (2)\n\
+             \x20   CHANGE_HOST_MODATTR;localhost;0\n\
+             \x20   CHANGE_SVC_MODATTR;localhost;HTTP;0\n"
+        );
+    }
+
+    #[test]
+    fn test_drift_report_unknown_bits() {
+        let mut status = NagiosStatus::parse_file("testdata/status.dat").unwrap();
+        status
+            .hosts
+            .get_mut("localhost")
+            .unwrap()
+            .modified_attributes = ModifiedAttributes::from(1 << 20);
+        assert_eq!(
+            status.drift_report().to_string(),
+            "unknown (0x100000) (1)\n\
+             \x20   CHANGE_HOST_MODATTR;localhost;0\n"
+        );
+    }
+
+    #[test]
+    fn test_drift_report_empty() {
+        let status = NagiosStatus::parse_file("testdata/status.dat").unwrap();
+        assert!(status.drift_report().is_empty());
+    }
+}
@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+
+use super::object::{Host, HostState, Service, ServiceState};
+use super::NagiosStatus;
+
+/// A service's host name and its position in that host's services.
+type ServicePos = (String, usize);
+
+/// Secondary indexes over `NagiosStatus`, built once after parsing. Every
+/// list is sorted by host name, then service description.
+#[derive(Debug, Default)]
+pub(super) struct Index {
+    services: HashMap<String, HashMap<String, usize>>,
+    host_states: HashMap<HostState, Vec<String>>,
+    service_states: HashMap<ServiceState, Vec<ServicePos>>,
+    host_check_commands: HashMap<String, Vec<String>>,
+    service_check_commands: HashMap<String, Vec<ServicePos>>,
+    host_check_periods: HashMap<String, Vec<String>>,
+    service_check_periods: HashMap<String, Vec<ServicePos>>,
+}
+
+/// `check_http!-p 8080` is indexed as `check_http`.
+fn command_name(check_command: &str) -> &str {
+    check_command
+        .split_once('!')
+        .map_or(check_command, |(name, _)| name)
+}
+
+impl Index {
+    /// Position of the service in its host's services.
+    pub(super) fn service_position(
+        &self,
+        host_name: &str,
+        service_description: &str,
+    ) -> Option<usize> {
+        self.services
+            .get(host_name)?
+            .get(service_description)
+            .copied()
+    }
+
+    pub(super) fn build(
+        hosts: &HashMap<String, Host>,
+        services: &HashMap<String, Vec<Service>>,
+    ) -> Index {
+        let mut index = Index::default();
+
+        let mut host_names: Vec<&String> = hosts.keys().collect();
+        host_names.sort();
+        for host in host_names.into_iter().map(|host_name| &hosts[host_name]) {
+            let host_name = || host.host_name.clone();
+            index
+                .host_states
+                .entry(host.current_state.clone())
+                .or_default()
+                .push(host_name());
+            index
+                .host_check_commands
+                .entry(command_name(&host.check_command).to_string())
+                .or_default()
+                .push(host_name());
+            index
+                .host_check_periods
+                .entry(host.check_period.clone())
+                .or_default()
+                .push(host_name());
+        }
+
+        let mut positions: Vec<(&Service, usize)> = services
+            .values()
+            .flat_map(|services| services.iter().enumerate())
+            .map(|(i, service)| (service, i))
+            .collect();
+        positions.sort_by(|(a, _), (b, _)| {
+            (&a.host_name, &a.service_description).cmp(&(&b.host_name, &b.service_description))
+        });
+        for (service, i) in positions {
+            let pos = || (service.host_name.clone(), i);
+            index
+                .services
+                .entry(service.host_name.clone())
+                .or_default()
+                .insert(service.service_description.clone(), i);
+            index
+                .service_states
+                .entry(service.current_state.clone())
+                .or_default()
+                .push(pos());
+            index
+                .service_check_commands
+                .entry(command_name(&service.check_command).to_string())
+                .or_default()
+                .push(pos());
+            index
+                .service_check_periods
+                .entry(service.check_period.clone())
+                .or_default()
+                .push(pos());
+        }
+
+        index
+    }
+}
+
+impl NagiosStatus {
+    /// Rebuilds the indexes after `hosts` or `services` changed.
+    pub(super) fn reindex(&mut self) {
+        self.index = Index::build(&self.hosts, &self.services);
+    }
+
+    fn indexed_hosts(&self, host_names: Option<&Vec<String>>) -> Vec<&Host> {
+        host_names
+            .into_iter()
+            .flatten()
+            .filter_map(|host_name| self.hosts.get(host_name))
+            .collect()
+    }
+
+    fn indexed_services(&self, positions: Option<&Vec<ServicePos>>) -> Vec<&Service> {
+        positions
+            .into_iter()
+            .flatten()
+            .filter_map(|(host_name, i)| self.services.get(host_name)?.get(*i))
+            .collect()
+    }
+
+    pub fn hosts_in_state(&self, state: &HostState) -> Vec<&Host> {
+        self.indexed_hosts(self.index.host_states.get(state))
+    }
+
+    pub fn services_in_state(&self, state: &ServiceState) -> Vec<&Service> {
+        self.indexed_services(self.index.service_states.get(state))
+    }
+
+    /// Hosts checked by `command`, ignoring arguments after `!`.
+    pub fn hosts_by_check_command(&self, command: &str) -> Vec<&Host> {
+        self.indexed_hosts(self.index.host_check_commands.get(command))
+    }
+
+    /// Services checked by `command`, ignoring arguments after `!`.
+    pub fn services_by_check_command(&self, command: &str) -> Vec<&Service> {
+        self.indexed_services(self.index.service_check_commands.get(command))
+    }
+
+    pub fn hosts_by_check_period(&self, check_period: &str) -> Vec<&Host> {
+        self.indexed_hosts(self.index.host_check_periods.get(check_period))
+    }
+
+    pub fn services_by_check_period(&self, check_period: &str) -> Vec<&Service> {
+        self.indexed_services(self.index.service_check_periods.get(check_period))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn descriptions(services: Vec<&Service>) -> Vec<&str> {
+        services
+            .iter()
+            .map(|service| service.service_description.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_by_state() {
+        let mut status = NagiosStatus::parse_file("testdata/status.dat").unwrap();
+        for service in status.services.get_mut("localhost").unwrap() {
+            if ["PING", "HTTP"].contains(&service.service_description.as_str()) {
+                service.current_state = ServiceState::Critical;
+            }
+        }
+        status.reindex();
+
+        assert_eq!(
+            descriptions(status.services_in_state(&ServiceState::Critical)),
+            vec!["HTTP", "PING"]
+        );
+        assert_eq!(status.services_in_state(&ServiceState::Ok).len(), 5);
+        assert!(status.services_in_state(&ServiceState::Unknown).is_empty());
+        assert_eq!(status.hosts_in_state(&HostState::Up).len(), 1);
+        assert!(status.hosts_in_state(&HostState::Down).is_empty());
+    }
+
+    #[test]
+    fn test_by_check_command_and_period() {
+        let status = NagiosStatus::parse_file("testdata/status.dat").unwrap();
+        assert_eq!(
+            descriptions(status.services_by_check_command("check_local_disk")),
+            vec!["Root Partition"]
+        );
+        assert_eq!(status.hosts_by_check_command("check-host-alive").len(), 1);
+        assert_eq!(status.services_by_check_period("24x7").len(), 7);
+        assert_eq!(status.hosts_by_check_period("24x7").len(), 1);
+        assert!(status.services_by_check_period("workhours").is_empty());
+        assert_eq!(command_name("check_http!-p 8080"), "check_http");
+    }
+}
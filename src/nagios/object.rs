@@ -1,12 +1,12 @@
-use chrono::{DateTime, NaiveDateTime, Utc};
-use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::collections::HashMap;
 use thiserror::Error;
 
 ////////////////////////////////////
 // filed types
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum CheckType {
     Active,  // 0
     Passive, // 1
@@ -15,14 +15,14 @@ pub enum CheckType {
     Other,   // 4
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum HostState {
     Up,          // 0
     Down,        // 1
     Unreachable, // 2
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum ServiceState {
     Ok,       // 0
     Warning,  // 1
@@ -30,37 +30,301 @@ pub enum ServiceState {
     Unknown,  // 3
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum AcknowledgementType {
     None,   // 0
     Normal, // 1
     Sticky, // 2
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum StateType {
     Soft, // 0
     Hard, // 1
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum CommentType {
+    User,            // 1
+    Downtime,        // 2
+    Flapping,        // 3
+    Acknowledgement, // 4
+}
+
+/// Adds `code()`, the number Nagios uses for each variant in status.dat,
+/// check result files and Livestatus.
+macro_rules! enum_code {
+    ($($ty:ident { $($variant:ident = $code:literal),* $(,)? })*) => {
+        $(
+            impl $ty {
+                pub fn code(&self) -> u32 {
+                    match self {
+                        $($ty::$variant => $code),*
+                    }
+                }
+            }
+        )*
+    };
+}
+
+enum_code! {
+    CheckType { Active = 0, Passive = 1, Parent = 2, File = 3, Other = 4 }
+    HostState { Up = 0, Down = 1, Unreachable = 2 }
+    ServiceState { Ok = 0, Warning = 1, Critical = 2, Unknown = 3 }
+    AcknowledgementType { None = 0, Normal = 1, Sticky = 2 }
+    StateType { Soft = 0, Hard = 1 }
+    CommentType { User = 1, Downtime = 2, Flapping = 3, Acknowledgement = 4 }
+}
+
+/// `modified_attributes` bits, i.e. the attributes changed at runtime by
+/// external commands (`MODATTR_*` in Nagios).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct ModifiedAttributes(u32);
 
+impl ModifiedAttributes {
+    pub const NONE: ModifiedAttributes = ModifiedAttributes(0);
+    pub const NOTIFICATIONS_ENABLED: ModifiedAttributes = ModifiedAttributes(1);
+    pub const ACTIVE_CHECKS_ENABLED: ModifiedAttributes = ModifiedAttributes(1 << 1);
+    pub const PASSIVE_CHECKS_ENABLED: ModifiedAttributes = ModifiedAttributes(1 << 2);
+    pub const EVENT_HANDLER_ENABLED: ModifiedAttributes = ModifiedAttributes(1 << 3);
+    pub const FLAP_DETECTION_ENABLED: ModifiedAttributes = ModifiedAttributes(1 << 4);
+    pub const FAILURE_PREDICTION_ENABLED: ModifiedAttributes = ModifiedAttributes(1 << 5);
+    pub const PERFORMANCE_DATA_ENABLED: ModifiedAttributes = ModifiedAttributes(1 << 6);
+    pub const OBSESSIVE_HANDLER_ENABLED: ModifiedAttributes = ModifiedAttributes(1 << 7);
+    pub const EVENT_HANDLER_COMMAND: ModifiedAttributes = ModifiedAttributes(1 << 8);
+    pub const CHECK_COMMAND: ModifiedAttributes = ModifiedAttributes(1 << 9);
+    pub const NORMAL_CHECK_INTERVAL: ModifiedAttributes = ModifiedAttributes(1 << 10);
+    pub const RETRY_CHECK_INTERVAL: ModifiedAttributes = ModifiedAttributes(1 << 11);
+    pub const MAX_CHECK_ATTEMPTS: ModifiedAttributes = ModifiedAttributes(1 << 12);
+    pub const FRESHNESS_CHECKS_ENABLED: ModifiedAttributes = ModifiedAttributes(1 << 13);
+    pub const CHECK_TIMEPERIOD: ModifiedAttributes = ModifiedAttributes(1 << 14);
+    pub const CUSTOM_VARIABLE: ModifiedAttributes = ModifiedAttributes(1 << 15);
+    pub const NOTIFICATION_TIMEPERIOD: ModifiedAttributes = ModifiedAttributes(1 << 16);
+
+    const FLAGS: &'static [(u32, &'static str)] = &[
+        (1, "MODATTR_NOTIFICATIONS_ENABLED"),
+        (1 << 1, "MODATTR_ACTIVE_CHECKS_ENABLED"),
+        (1 << 2, "MODATTR_PASSIVE_CHECKS_ENABLED"),
+        (1 << 3, "MODATTR_EVENT_HANDLER_ENABLED"),
+        (1 << 4, "MODATTR_FLAP_DETECTION_ENABLED"),
+        (1 << 5, "MODATTR_FAILURE_PREDICTION_ENABLED"),
+        (1 << 6, "MODATTR_PERFORMANCE_DATA_ENABLED"),
+        (1 << 7, "MODATTR_OBSESSIVE_HANDLER_ENABLED"),
+        (1 << 8, "MODATTR_EVENT_HANDLER_COMMAND"),
+        (1 << 9, "MODATTR_CHECK_COMMAND"),
+        (1 << 10, "MODATTR_NORMAL_CHECK_INTERVAL"),
+        (1 << 11, "MODATTR_RETRY_CHECK_INTERVAL"),
+        (1 << 12, "MODATTR_MAX_CHECK_ATTEMPTS"),
+        (1 << 13, "MODATTR_FRESHNESS_CHECKS_ENABLED"),
+        (1 << 14, "MODATTR_CHECK_TIMEPERIOD"),
+        (1 << 15, "MODATTR_CUSTOM_VARIABLE"),
+        (1 << 16, "MODATTR_NOTIFICATION_TIMEPERIOD"),
+    ];
+
+    pub fn bits(&self) -> u32 {
+        self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Whether every flag set in `other` is also set in `self`.
+    pub fn contains(&self, other: ModifiedAttributes) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Known flags that are set, one at a time.
+    pub fn iter(&self) -> impl Iterator<Item = ModifiedAttributes> {
+        known_flags(self.0, Self::FLAGS).map(|(bit, _)| ModifiedAttributes(bit))
+    }
+
+    /// Nagios names of the known flags that are set, e.g. `MODATTR_CHECK_COMMAND`.
+    pub fn names(&self) -> impl Iterator<Item = &'static str> {
+        known_flags(self.0, Self::FLAGS).map(|(_, name)| name)
+    }
+
+    /// Set bits that match no known flag.
+    pub fn unknown_bits(&self) -> u32 {
+        unknown_bits(self.0, Self::FLAGS)
+    }
+}
+
 impl From<u32> for ModifiedAttributes {
     fn from(u: u32) -> Self {
         ModifiedAttributes(u)
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+impl std::ops::BitOr for ModifiedAttributes {
+    type Output = ModifiedAttributes;
+
+    fn bitor(self, rhs: ModifiedAttributes) -> ModifiedAttributes {
+        ModifiedAttributes(self.0 | rhs.0)
+    }
+}
+
+impl std::fmt::Display for ModifiedAttributes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt_flags(self.0, Self::FLAGS, "MODATTR_NONE", f)
+    }
+}
+
+impl Serialize for ModifiedAttributes {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_flags(self.0, Self::FLAGS, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ModifiedAttributes {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_flags(Self::FLAGS, deserializer).map(ModifiedAttributes)
+    }
+}
+
+/// `check_options` bits (`CHECK_OPTION_*` in Nagios).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct CheckOptions(u32);
 
+impl CheckOptions {
+    pub const NONE: CheckOptions = CheckOptions(0);
+    pub const FORCE_EXECUTION: CheckOptions = CheckOptions(1);
+    pub const FRESHNESS_CHECK: CheckOptions = CheckOptions(1 << 1);
+    pub const ORPHAN_CHECK: CheckOptions = CheckOptions(1 << 2);
+    pub const DEPENDENCY_CHECK: CheckOptions = CheckOptions(1 << 3);
+
+    const FLAGS: &'static [(u32, &'static str)] = &[
+        (1, "CHECK_OPTION_FORCE_EXECUTION"),
+        (1 << 1, "CHECK_OPTION_FRESHNESS_CHECK"),
+        (1 << 2, "CHECK_OPTION_ORPHAN_CHECK"),
+        (1 << 3, "CHECK_OPTION_DEPENDENCY_CHECK"),
+    ];
+
+    pub fn bits(&self) -> u32 {
+        self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Whether every flag set in `other` is also set in `self`.
+    pub fn contains(&self, other: CheckOptions) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Known flags that are set, one at a time.
+    pub fn iter(&self) -> impl Iterator<Item = CheckOptions> {
+        known_flags(self.0, Self::FLAGS).map(|(bit, _)| CheckOptions(bit))
+    }
+
+    /// Nagios names of the known flags that are set, e.g. `CHECK_OPTION_ORPHAN_CHECK`.
+    pub fn names(&self) -> impl Iterator<Item = &'static str> {
+        known_flags(self.0, Self::FLAGS).map(|(_, name)| name)
+    }
+}
+
 impl From<u32> for CheckOptions {
     fn from(u: u32) -> Self {
         CheckOptions(u)
     }
 }
 
+impl std::ops::BitOr for CheckOptions {
+    type Output = CheckOptions;
+
+    fn bitor(self, rhs: CheckOptions) -> CheckOptions {
+        CheckOptions(self.0 | rhs.0)
+    }
+}
+
+impl std::fmt::Display for CheckOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt_flags(self.0, Self::FLAGS, "CHECK_OPTION_NONE", f)
+    }
+}
+
+impl Serialize for CheckOptions {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_flags(self.0, Self::FLAGS, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for CheckOptions {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_flags(Self::FLAGS, deserializer).map(CheckOptions)
+    }
+}
+
+fn known_flags(
+    bits: u32,
+    flags: &'static [(u32, &'static str)],
+) -> impl Iterator<Item = (u32, &'static str)> {
+    flags
+        .iter()
+        .copied()
+        .filter(move |(bit, _)| bits & bit != 0)
+}
+
+/// Formats as `A|B`, with unknown bits appended in hex.
+fn fmt_flags(
+    bits: u32,
+    flags: &'static [(u32, &'static str)],
+    none: &str,
+    f: &mut std::fmt::Formatter<'_>,
+) -> std::fmt::Result {
+    if bits == 0 {
+        return write!(f, "{}", none);
+    }
+    let mut parts: Vec<String> = known_flags(bits, flags)
+        .map(|(_, name)| name.to_string())
+        .collect();
+    let unknown = unknown_bits(bits, flags);
+    if unknown != 0 {
+        parts.push(format!("{:#x}", unknown));
+    }
+    write!(f, "{}", parts.join("|"))
+}
+
+fn unknown_bits(bits: u32, flags: &'static [(u32, &'static str)]) -> u32 {
+    flags.iter().fold(bits, |rest, (bit, _)| rest & !bit)
+}
+
+/// Flags are (de)serialized as a list of names, with unknown bits as one
+/// last element in hex, e.g. `["MODATTR_CHECK_COMMAND", "0x20000"]`.
+fn serialize_flags<S: Serializer>(
+    bits: u32,
+    flags: &'static [(u32, &'static str)],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let mut names: Vec<String> = known_flags(bits, flags)
+        .map(|(_, name)| name.to_string())
+        .collect();
+    let unknown = unknown_bits(bits, flags);
+    if unknown != 0 {
+        names.push(format!("{:#x}", unknown));
+    }
+    serializer.collect_seq(names)
+}
+
+fn deserialize_flags<'de, D: Deserializer<'de>>(
+    flags: &'static [(u32, &'static str)],
+    deserializer: D,
+) -> Result<u32, D::Error> {
+    let names = Vec::<String>::deserialize(deserializer)?;
+    names.iter().try_fold(0, |bits, name| {
+        let bit = match name.strip_prefix("0x") {
+            Some(hex) => u32::from_str_radix(hex, 16).ok(),
+            None => flags
+                .iter()
+                .find(|(_, flag_name)| flag_name == name)
+                .map(|(bit, _)| *bit),
+        };
+        bit.map(|bit| bits | bit)
+            .ok_or_else(|| serde::de::Error::custom(format!("unknown flag: {}", name)))
+    })
+}
+
 ////////////////////////////////////
 // error
 
@@ -82,79 +346,101 @@ pub enum ConvertError {
     InvalidAcknowledgementTypeValue(String),
     #[error("invalid state type value: {0}")]
     InvalidStateTypeValue(String),
+    #[error("invalid comment type value: {0}")]
+    InvalidCommentTypeValue(String),
 }
 
 ////////////////////////////////////
 // nagios status
 
-fn get_raw<'a>(
+/// The keys and values of a block, owned or borrowed from the buffer being
+/// parsed.
+pub(super) trait KeyValues {
+    fn get_value(&self, key: &str) -> Option<&str>;
+
+    fn into_map(self) -> HashMap<String, String>;
+}
+
+impl KeyValues for HashMap<String, String> {
+    fn get_value(&self, key: &str) -> Option<&str> {
+        self.get(key).map(String::as_str)
+    }
+
+    fn into_map(self) -> HashMap<String, String> {
+        self
+    }
+}
+
+impl KeyValues for &HashMap<&str, &str> {
+    fn get_value(&self, key: &str) -> Option<&str> {
+        self.get(key).copied()
+    }
+
+    fn into_map(self) -> HashMap<String, String> {
+        self.iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+}
+
+fn get_raw<'a, K: KeyValues>(
     key: &str,
-    key_values: &'a HashMap<String, String>,
-) -> std::result::Result<&'a String, ConvertError> {
+    key_values: &'a K,
+) -> std::result::Result<&'a str, ConvertError> {
     key_values
-        .get(key)
-        .ok_or(ConvertError::KeyDoesNotExists(key.into()))
+        .get_value(key)
+        .ok_or_else(|| ConvertError::KeyDoesNotExists(key.into()))
 }
 
-fn get_bool(
-    key: &str,
-    key_values: &HashMap<String, String>,
-) -> std::result::Result<bool, ConvertError> {
-    match get_raw(key, key_values)?.as_str() {
+fn get_bool<K: KeyValues>(key: &str, key_values: &K) -> std::result::Result<bool, ConvertError> {
+    match get_raw(key, key_values)? {
         "0" => Ok(false),
         "1" => Ok(true),
         s => Err(ConvertError::InvalidBooleanValue(s.into())),
     }
 }
 
-fn get_string(
+fn get_string<K: KeyValues>(
     key: &str,
-    key_values: &HashMap<String, String>,
+    key_values: &K,
 ) -> std::result::Result<String, ConvertError> {
     let s = get_raw(key, key_values)?;
     Ok(s.into())
 }
 
-fn get_u32(
-    key: &str,
-    key_values: &HashMap<String, String>,
-) -> std::result::Result<u32, ConvertError> {
+fn get_u32<K: KeyValues>(key: &str, key_values: &K) -> std::result::Result<u32, ConvertError> {
     let s = get_raw(key, key_values)?;
     s.parse::<u32>()
         .map_err(|_| ConvertError::FailedToParse(s.to_string(), "u32".to_string()))
 }
 
-fn get_f64(
-    key: &str,
-    key_values: &HashMap<String, String>,
-) -> std::result::Result<f64, ConvertError> {
+fn get_f64<K: KeyValues>(key: &str, key_values: &K) -> std::result::Result<f64, ConvertError> {
     let s = get_raw(key, key_values)?;
     s.parse::<f64>()
         .map_err(|_| ConvertError::FailedToParse(s.to_string(), "f64".to_string()))
 }
 
-fn get_datetime(
+fn get_datetime<K: KeyValues>(
     key: &str,
-    key_values: &HashMap<String, String>,
+    key_values: &K,
 ) -> std::result::Result<Option<DateTime<Utc>>, ConvertError> {
     let s = get_raw(key, key_values)?;
-    if s.as_str() == "0" {
+    if s == "0" {
         return Ok(None);
     }
     let timestamp = s
         .parse::<i64>()
         .map_err(|_| ConvertError::FailedToParse(s.to_string(), "DateTime<Utc>".to_string()))?;
-    Ok(Some(DateTime::<Utc>::from_utc(
-        NaiveDateTime::from_timestamp(timestamp, 0),
-        Utc,
-    )))
+    DateTime::<Utc>::from_timestamp(timestamp, 0)
+        .map(Some)
+        .ok_or_else(|| ConvertError::FailedToParse(s.to_string(), "DateTime<Utc>".to_string()))
 }
 
-fn get_check_type(
+fn get_check_type<K: KeyValues>(
     key: &str,
-    key_values: &HashMap<String, String>,
+    key_values: &K,
 ) -> std::result::Result<CheckType, ConvertError> {
-    match get_raw(key, key_values)?.as_str() {
+    match get_raw(key, key_values)? {
         "0" => Ok(CheckType::Active),
         "1" => Ok(CheckType::Passive),
         "2" => Ok(CheckType::Parent),
@@ -164,11 +450,11 @@ fn get_check_type(
     }
 }
 
-fn get_host_state(
+fn get_host_state<K: KeyValues>(
     key: &str,
-    key_values: &HashMap<String, String>,
+    key_values: &K,
 ) -> std::result::Result<HostState, ConvertError> {
-    match get_raw(key, key_values)?.as_str() {
+    match get_raw(key, key_values)? {
         "0" => Ok(HostState::Up),
         "1" => Ok(HostState::Down),
         "2" => Ok(HostState::Unreachable),
@@ -176,11 +462,11 @@ fn get_host_state(
     }
 }
 
-fn get_service_state(
+fn get_service_state<K: KeyValues>(
     key: &str,
-    key_values: &HashMap<String, String>,
+    key_values: &K,
 ) -> std::result::Result<ServiceState, ConvertError> {
-    match get_raw(key, key_values)?.as_str() {
+    match get_raw(key, key_values)? {
         "0" => Ok(ServiceState::Ok),
         "1" => Ok(ServiceState::Warning),
         "2" => Ok(ServiceState::Critical),
@@ -189,11 +475,11 @@ fn get_service_state(
     }
 }
 
-fn get_acknowledgement_type(
+fn get_acknowledgement_type<K: KeyValues>(
     key: &str,
-    key_values: &HashMap<String, String>,
+    key_values: &K,
 ) -> std::result::Result<AcknowledgementType, ConvertError> {
-    match get_raw(key, key_values)?.as_str() {
+    match get_raw(key, key_values)? {
         "0" => Ok(AcknowledgementType::None),
         "1" => Ok(AcknowledgementType::Normal),
         "2" => Ok(AcknowledgementType::Sticky),
@@ -201,17 +487,30 @@ fn get_acknowledgement_type(
     }
 }
 
-fn get_state_type(
+fn get_state_type<K: KeyValues>(
     key: &str,
-    key_values: &HashMap<String, String>,
+    key_values: &K,
 ) -> std::result::Result<StateType, ConvertError> {
-    match get_raw(key, key_values)?.as_str() {
+    match get_raw(key, key_values)? {
         "0" => Ok(StateType::Soft),
         "1" => Ok(StateType::Hard),
         s => Err(ConvertError::InvalidStateTypeValue(s.into())),
     }
 }
 
+fn get_comment_type<K: KeyValues>(
+    key: &str,
+    key_values: &K,
+) -> std::result::Result<CommentType, ConvertError> {
+    match get_raw(key, key_values)? {
+        "1" => Ok(CommentType::User),
+        "2" => Ok(CommentType::Downtime),
+        "3" => Ok(CommentType::Flapping),
+        "4" => Ok(CommentType::Acknowledgement),
+        s => Err(ConvertError::InvalidCommentTypeValue(s.into())),
+    }
+}
+
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct Host {
     pub host_name: String,
@@ -269,54 +568,62 @@ impl TryFrom<HashMap<String, String>> for Host {
     type Error = ConvertError;
 
     fn try_from(key_values: HashMap<String, String>) -> std::result::Result<Self, Self::Error> {
+        Host::from_key_values(&key_values)
+    }
+}
+
+impl Host {
+    pub(super) fn from_key_values<K: KeyValues>(
+        key_values: &K,
+    ) -> std::result::Result<Host, ConvertError> {
         Ok(Host {
-            host_name: get_string("host_name", &key_values)?,
-            modified_attributes: get_u32("modified_attributes", &key_values)?.into(),
-            check_command: get_string("check_command", &key_values)?,
-            check_period: get_string("check_period", &key_values)?,
-            notification_period: get_string("notification_period", &key_values)?,
-            importance: get_u32("importance", &key_values)?,
-            check_interval: get_f64("check_interval", &key_values)?,
-            retry_interval: get_f64("retry_interval", &key_values)?,
-            event_handler: get_string("event_handler", &key_values)?,
-            has_been_checked: get_bool("has_been_checked", &key_values)?,
-            should_be_scheduled: get_bool("should_be_scheduled", &key_values)?,
-            check_execution_time: get_f64("check_execution_time", &key_values)?,
-            check_latency: get_f64("check_latency", &key_values)?,
-            check_type: get_check_type("check_type", &key_values)?,
-            current_state: get_host_state("current_state", &key_values)?,
-            last_hard_state: get_host_state("last_hard_state", &key_values)?,
-            plugin_output: get_string("plugin_output", &key_values)?,
-            long_plugin_output: get_string("long_plugin_output", &key_values)?,
-            performance_data: get_string("performance_data", &key_values)?,
-            last_check: get_datetime("last_check", &key_values)?,
-            next_check: get_datetime("next_check", &key_values)?,
-            check_options: get_u32("check_options", &key_values)?.into(),
-            current_attempt: get_u32("current_attempt", &key_values)?,
-            max_attempts: get_u32("max_attempts", &key_values)?,
-            state_type: get_state_type("state_type", &key_values)?,
-            last_state_change: get_datetime("last_state_change", &key_values)?,
-            last_hard_state_change: get_datetime("last_hard_state_change", &key_values)?,
-            last_time_up: get_datetime("last_time_up", &key_values)?,
-            last_time_down: get_datetime("last_time_down", &key_values)?,
-            last_time_unreachable: get_datetime("last_time_unreachable", &key_values)?,
-            last_notification: get_datetime("last_notification", &key_values)?,
-            next_notification: get_datetime("next_notification", &key_values)?,
-            no_more_notifications: get_bool("no_more_notifications", &key_values)?,
-            current_notification_number: get_u32("current_notification_number", &key_values)?,
-            notifications_enabled: get_bool("notifications_enabled", &key_values)?,
-            problem_has_been_acknowledged: get_bool("problem_has_been_acknowledged", &key_values)?,
-            acknowledgement_type: get_acknowledgement_type("acknowledgement_type", &key_values)?,
-            active_checks_enabled: get_bool("active_checks_enabled", &key_values)?,
-            passive_checks_enabled: get_bool("passive_checks_enabled", &key_values)?,
-            event_handler_enabled: get_bool("event_handler_enabled", &key_values)?,
-            flap_detection_enabled: get_bool("flap_detection_enabled", &key_values)?,
-            process_performance_data: get_bool("process_performance_data", &key_values)?,
-            obsess: get_bool("obsess", &key_values)?,
-            last_update: get_datetime("last_update", &key_values)?,
-            is_flapping: get_bool("is_flapping", &key_values)?,
-            percent_state_change: get_f64("percent_state_change", &key_values)?,
-            scheduled_downtime_depth: get_u32("scheduled_downtime_depth", &key_values)?,
+            host_name: get_string("host_name", key_values)?,
+            modified_attributes: get_u32("modified_attributes", key_values)?.into(),
+            check_command: get_string("check_command", key_values)?,
+            check_period: get_string("check_period", key_values)?,
+            notification_period: get_string("notification_period", key_values)?,
+            importance: get_u32("importance", key_values)?,
+            check_interval: get_f64("check_interval", key_values)?,
+            retry_interval: get_f64("retry_interval", key_values)?,
+            event_handler: get_string("event_handler", key_values)?,
+            has_been_checked: get_bool("has_been_checked", key_values)?,
+            should_be_scheduled: get_bool("should_be_scheduled", key_values)?,
+            check_execution_time: get_f64("check_execution_time", key_values)?,
+            check_latency: get_f64("check_latency", key_values)?,
+            check_type: get_check_type("check_type", key_values)?,
+            current_state: get_host_state("current_state", key_values)?,
+            last_hard_state: get_host_state("last_hard_state", key_values)?,
+            plugin_output: get_string("plugin_output", key_values)?,
+            long_plugin_output: get_string("long_plugin_output", key_values)?,
+            performance_data: get_string("performance_data", key_values)?,
+            last_check: get_datetime("last_check", key_values)?,
+            next_check: get_datetime("next_check", key_values)?,
+            check_options: get_u32("check_options", key_values)?.into(),
+            current_attempt: get_u32("current_attempt", key_values)?,
+            max_attempts: get_u32("max_attempts", key_values)?,
+            state_type: get_state_type("state_type", key_values)?,
+            last_state_change: get_datetime("last_state_change", key_values)?,
+            last_hard_state_change: get_datetime("last_hard_state_change", key_values)?,
+            last_time_up: get_datetime("last_time_up", key_values)?,
+            last_time_down: get_datetime("last_time_down", key_values)?,
+            last_time_unreachable: get_datetime("last_time_unreachable", key_values)?,
+            last_notification: get_datetime("last_notification", key_values)?,
+            next_notification: get_datetime("next_notification", key_values)?,
+            no_more_notifications: get_bool("no_more_notifications", key_values)?,
+            current_notification_number: get_u32("current_notification_number", key_values)?,
+            notifications_enabled: get_bool("notifications_enabled", key_values)?,
+            problem_has_been_acknowledged: get_bool("problem_has_been_acknowledged", key_values)?,
+            acknowledgement_type: get_acknowledgement_type("acknowledgement_type", key_values)?,
+            active_checks_enabled: get_bool("active_checks_enabled", key_values)?,
+            passive_checks_enabled: get_bool("passive_checks_enabled", key_values)?,
+            event_handler_enabled: get_bool("event_handler_enabled", key_values)?,
+            flap_detection_enabled: get_bool("flap_detection_enabled", key_values)?,
+            process_performance_data: get_bool("process_performance_data", key_values)?,
+            obsess: get_bool("obsess", key_values)?,
+            last_update: get_datetime("last_update", key_values)?,
+            is_flapping: get_bool("is_flapping", key_values)?,
+            percent_state_change: get_f64("percent_state_change", key_values)?,
+            scheduled_downtime_depth: get_u32("scheduled_downtime_depth", key_values)?,
         })
     }
 }
@@ -380,61 +687,172 @@ impl TryFrom<HashMap<String, String>> for Service {
     type Error = ConvertError;
 
     fn try_from(key_values: HashMap<String, String>) -> std::result::Result<Self, Self::Error> {
+        Service::from_key_values(&key_values)
+    }
+}
+
+impl Service {
+    pub(super) fn from_key_values<K: KeyValues>(
+        key_values: &K,
+    ) -> std::result::Result<Service, ConvertError> {
         Ok(Service {
-            host_name: get_string("host_name", &key_values)?,
-            service_description: get_string("service_description", &key_values)?,
-            modified_attributes: get_u32("modified_attributes", &key_values)?.into(),
-            check_command: get_string("check_command", &key_values)?,
-            check_period: get_string("check_period", &key_values)?,
-            notification_period: get_string("notification_period", &key_values)?,
-            importance: get_u32("importance", &key_values)?,
-            check_interval: get_f64("check_interval", &key_values)?,
-            retry_interval: get_f64("retry_interval", &key_values)?,
-            event_handler: get_string("event_handler", &key_values)?,
-            has_been_checked: get_bool("has_been_checked", &key_values)?,
-            should_be_scheduled: get_bool("should_be_scheduled", &key_values)?,
-            check_execution_time: get_f64("check_execution_time", &key_values)?,
-            check_latency: get_f64("check_latency", &key_values)?,
-            check_type: get_check_type("check_type", &key_values)?,
-            current_state: get_service_state("current_state", &key_values)?,
-            last_hard_state: get_service_state("last_hard_state", &key_values)?,
-            plugin_output: get_string("plugin_output", &key_values)?,
-            long_plugin_output: get_string("long_plugin_output", &key_values)?,
-            performance_data: get_string("performance_data", &key_values)?,
-            last_check: get_datetime("last_check", &key_values)?,
-            next_check: get_datetime("next_check", &key_values)?,
-            check_options: get_u32("check_options", &key_values)?.into(),
-            current_attempt: get_u32("current_attempt", &key_values)?,
-            max_attempts: get_u32("max_attempts", &key_values)?,
-            state_type: get_state_type("state_type", &key_values)?,
-            last_state_change: get_datetime("last_state_change", &key_values)?,
-            last_hard_state_change: get_datetime("last_hard_state_change", &key_values)?,
-            last_time_ok: get_datetime("last_time_ok", &key_values)?,
-            last_time_warning: get_datetime("last_time_warning", &key_values)?,
-            last_time_critical: get_datetime("last_time_critical", &key_values)?,
-            last_time_unknown: get_datetime("last_time_unknown", &key_values)?,
-            last_notification: get_datetime("last_notification", &key_values)?,
-            next_notification: get_datetime("next_notification", &key_values)?,
-            no_more_notifications: get_bool("no_more_notifications", &key_values)?,
-            current_notification_number: get_u32("current_notification_number", &key_values)?,
-            notifications_enabled: get_bool("notifications_enabled", &key_values)?,
-            problem_has_been_acknowledged: get_bool("problem_has_been_acknowledged", &key_values)?,
-            acknowledgement_type: get_acknowledgement_type("acknowledgement_type", &key_values)?,
-            active_checks_enabled: get_bool("active_checks_enabled", &key_values)?,
-            passive_checks_enabled: get_bool("passive_checks_enabled", &key_values)?,
-            event_handler_enabled: get_bool("event_handler_enabled", &key_values)?,
-            flap_detection_enabled: get_bool("flap_detection_enabled", &key_values)?,
-            process_performance_data: get_bool("process_performance_data", &key_values)?,
-            obsess: get_bool("obsess", &key_values)?,
-            last_update: get_datetime("last_update", &key_values)?,
-            is_flapping: get_bool("is_flapping", &key_values)?,
-            percent_state_change: get_f64("percent_state_change", &key_values)?,
-            scheduled_downtime_depth: get_u32("scheduled_downtime_depth", &key_values)?,
+            host_name: get_string("host_name", key_values)?,
+            service_description: get_string("service_description", key_values)?,
+            modified_attributes: get_u32("modified_attributes", key_values)?.into(),
+            check_command: get_string("check_command", key_values)?,
+            check_period: get_string("check_period", key_values)?,
+            notification_period: get_string("notification_period", key_values)?,
+            importance: get_u32("importance", key_values)?,
+            check_interval: get_f64("check_interval", key_values)?,
+            retry_interval: get_f64("retry_interval", key_values)?,
+            event_handler: get_string("event_handler", key_values)?,
+            has_been_checked: get_bool("has_been_checked", key_values)?,
+            should_be_scheduled: get_bool("should_be_scheduled", key_values)?,
+            check_execution_time: get_f64("check_execution_time", key_values)?,
+            check_latency: get_f64("check_latency", key_values)?,
+            check_type: get_check_type("check_type", key_values)?,
+            current_state: get_service_state("current_state", key_values)?,
+            last_hard_state: get_service_state("last_hard_state", key_values)?,
+            plugin_output: get_string("plugin_output", key_values)?,
+            long_plugin_output: get_string("long_plugin_output", key_values)?,
+            performance_data: get_string("performance_data", key_values)?,
+            last_check: get_datetime("last_check", key_values)?,
+            next_check: get_datetime("next_check", key_values)?,
+            check_options: get_u32("check_options", key_values)?.into(),
+            current_attempt: get_u32("current_attempt", key_values)?,
+            max_attempts: get_u32("max_attempts", key_values)?,
+            state_type: get_state_type("state_type", key_values)?,
+            last_state_change: get_datetime("last_state_change", key_values)?,
+            last_hard_state_change: get_datetime("last_hard_state_change", key_values)?,
+            last_time_ok: get_datetime("last_time_ok", key_values)?,
+            last_time_warning: get_datetime("last_time_warning", key_values)?,
+            last_time_critical: get_datetime("last_time_critical", key_values)?,
+            last_time_unknown: get_datetime("last_time_unknown", key_values)?,
+            last_notification: get_datetime("last_notification", key_values)?,
+            next_notification: get_datetime("next_notification", key_values)?,
+            no_more_notifications: get_bool("no_more_notifications", key_values)?,
+            current_notification_number: get_u32("current_notification_number", key_values)?,
+            notifications_enabled: get_bool("notifications_enabled", key_values)?,
+            problem_has_been_acknowledged: get_bool("problem_has_been_acknowledged", key_values)?,
+            acknowledgement_type: get_acknowledgement_type("acknowledgement_type", key_values)?,
+            active_checks_enabled: get_bool("active_checks_enabled", key_values)?,
+            passive_checks_enabled: get_bool("passive_checks_enabled", key_values)?,
+            event_handler_enabled: get_bool("event_handler_enabled", key_values)?,
+            flap_detection_enabled: get_bool("flap_detection_enabled", key_values)?,
+            process_performance_data: get_bool("process_performance_data", key_values)?,
+            obsess: get_bool("obsess", key_values)?,
+            last_update: get_datetime("last_update", key_values)?,
+            is_flapping: get_bool("is_flapping", key_values)?,
+            percent_state_change: get_f64("percent_state_change", key_values)?,
+            scheduled_downtime_depth: get_u32("scheduled_downtime_depth", key_values)?,
+        })
+    }
+}
+
+/// A `hostcomment` or `servicecomment` block.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Comment {
+    pub host_name: String,
+    /// `None` for host comments.
+    pub service_description: Option<String>,
+    pub entry_type: CommentType,
+    pub comment_id: u32,
+    pub source: u32,
+    pub persistent: bool,
+    pub entry_time: Option<DateTime<Utc>>,
+    pub expires: bool,
+    pub expire_time: Option<DateTime<Utc>>,
+    pub author: String,
+    pub comment_data: String,
+}
+
+impl TryFrom<HashMap<String, String>> for Comment {
+    type Error = ConvertError;
+
+    fn try_from(key_values: HashMap<String, String>) -> std::result::Result<Self, Self::Error> {
+        Comment::from_key_values(&key_values)
+    }
+}
+
+impl Comment {
+    pub(super) fn from_key_values<K: KeyValues>(
+        key_values: &K,
+    ) -> std::result::Result<Comment, ConvertError> {
+        Ok(Comment {
+            host_name: get_string("host_name", key_values)?,
+            service_description: key_values
+                .get_value("service_description")
+                .map(str::to_string),
+            entry_type: get_comment_type("entry_type", key_values)?,
+            comment_id: get_u32("comment_id", key_values)?,
+            source: get_u32("source", key_values)?,
+            persistent: get_bool("persistent", key_values)?,
+            entry_time: get_datetime("entry_time", key_values)?,
+            expires: get_bool("expires", key_values)?,
+            expire_time: get_datetime("expire_time", key_values)?,
+            author: get_string("author", key_values)?,
+            comment_data: get_string("comment_data", key_values)?,
+        })
+    }
+}
+
+/// A `hostdowntime` or `servicedowntime` block.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Downtime {
+    pub host_name: String,
+    /// `None` for host downtimes.
+    pub service_description: Option<String>,
+    pub downtime_id: u32,
+    pub comment_id: u32,
+    pub entry_time: Option<DateTime<Utc>>,
+    pub start_time: Option<DateTime<Utc>>,
+    pub flex_downtime_start: Option<DateTime<Utc>>,
+    pub end_time: Option<DateTime<Utc>>,
+    pub triggered_by: u32,
+    pub fixed: bool,
+    pub duration: u32,
+    pub is_in_effect: bool,
+    pub start_notification_sent: bool,
+    pub author: String,
+    pub comment: String,
+}
+
+impl TryFrom<HashMap<String, String>> for Downtime {
+    type Error = ConvertError;
+
+    fn try_from(key_values: HashMap<String, String>) -> std::result::Result<Self, Self::Error> {
+        Downtime::from_key_values(&key_values)
+    }
+}
+
+impl Downtime {
+    pub(super) fn from_key_values<K: KeyValues>(
+        key_values: &K,
+    ) -> std::result::Result<Downtime, ConvertError> {
+        Ok(Downtime {
+            host_name: get_string("host_name", key_values)?,
+            service_description: key_values
+                .get_value("service_description")
+                .map(str::to_string),
+            downtime_id: get_u32("downtime_id", key_values)?,
+            comment_id: get_u32("comment_id", key_values)?,
+            entry_time: get_datetime("entry_time", key_values)?,
+            start_time: get_datetime("start_time", key_values)?,
+            flex_downtime_start: get_datetime("flex_downtime_start", key_values)?,
+            end_time: get_datetime("end_time", key_values)?,
+            triggered_by: get_u32("triggered_by", key_values)?,
+            fixed: get_bool("fixed", key_values)?,
+            duration: get_u32("duration", key_values)?,
+            is_in_effect: get_bool("is_in_effect", key_values)?,
+            start_notification_sent: get_bool("start_notification_sent", key_values)?,
+            author: get_string("author", key_values)?,
+            comment: get_string("comment", key_values)?,
         })
     }
 }
 
 #[cfg(test)]
+#[allow(deprecated, clippy::bool_assert_comparison)]
 mod tests {
     use super::*;
     use chrono::TimeZone;
@@ -558,6 +976,9 @@ mod tests {
             let key_values: HashMap<String, String> =
                 HashMap::from([("key".into(), test_case.0.into())]);
             assert_eq!(get_check_type("key", &key_values), test_case.1);
+            if let Ok(value) = test_case.1 {
+                assert_eq!(value.code().to_string(), test_case.0);
+            }
         }
     }
 
@@ -577,6 +998,9 @@ mod tests {
             let key_values: HashMap<String, String> =
                 HashMap::from([("key".into(), test_case.0.into())]);
             assert_eq!(get_host_state("key", &key_values), test_case.1);
+            if let Ok(value) = test_case.1 {
+                assert_eq!(value.code().to_string(), test_case.0);
+            }
         }
     }
 
@@ -597,6 +1021,9 @@ mod tests {
             let key_values: HashMap<String, String> =
                 HashMap::from([("key".into(), test_case.0.into())]);
             assert_eq!(get_service_state("key", &key_values), test_case.1);
+            if let Ok(value) = test_case.1 {
+                assert_eq!(value.code().to_string(), test_case.0);
+            }
         }
     }
 
@@ -616,6 +1043,9 @@ mod tests {
             let key_values: HashMap<String, String> =
                 HashMap::from([("key".into(), test_case.0.into())]);
             assert_eq!(get_acknowledgement_type("key", &key_values), test_case.1);
+            if let Ok(value) = test_case.1 {
+                assert_eq!(value.code().to_string(), test_case.0);
+            }
         }
     }
 
@@ -634,7 +1064,99 @@ mod tests {
             let key_values: HashMap<String, String> =
                 HashMap::from([("key".into(), test_case.0.into())]);
             assert_eq!(get_state_type("key", &key_values), test_case.1);
+            if let Ok(value) = test_case.1 {
+                assert_eq!(value.code().to_string(), test_case.0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_modified_attributes() {
+        let attributes = ModifiedAttributes::from(1 | 1 << 9 | 1 << 20);
+        assert!(attributes.contains(ModifiedAttributes::NOTIFICATIONS_ENABLED));
+        assert!(attributes.contains(
+            ModifiedAttributes::NOTIFICATIONS_ENABLED | ModifiedAttributes::CHECK_COMMAND
+        ));
+        assert!(!attributes.contains(ModifiedAttributes::ACTIVE_CHECKS_ENABLED));
+        assert_eq!(
+            attributes.iter().collect::<Vec<_>>(),
+            vec![
+                ModifiedAttributes::NOTIFICATIONS_ENABLED,
+                ModifiedAttributes::CHECK_COMMAND
+            ]
+        );
+        assert_eq!(
+            attributes.to_string(),
+            "MODATTR_NOTIFICATIONS_ENABLED|MODATTR_CHECK_COMMAND|0x100000"
+        );
+        assert_eq!(ModifiedAttributes::NONE.to_string(), "MODATTR_NONE");
+    }
+
+    #[test]
+    fn test_check_options() {
+        struct TestCase(u32, &'static str, Vec<&'static str>);
+        let test_cases = vec![
+            TestCase(0, "CHECK_OPTION_NONE", vec![]),
+            TestCase(
+                1,
+                "CHECK_OPTION_FORCE_EXECUTION",
+                vec!["CHECK_OPTION_FORCE_EXECUTION"],
+            ),
+            TestCase(
+                6,
+                "CHECK_OPTION_FRESHNESS_CHECK|CHECK_OPTION_ORPHAN_CHECK",
+                vec!["CHECK_OPTION_FRESHNESS_CHECK", "CHECK_OPTION_ORPHAN_CHECK"],
+            ),
+        ];
+        for test_case in test_cases {
+            let options = CheckOptions::from(test_case.0);
+            assert_eq!(options.to_string(), test_case.1);
+            assert_eq!(options.names().collect::<Vec<_>>(), test_case.2);
+        }
+    }
+
+    #[test]
+    fn test_flags_serde() {
+        let attributes =
+            ModifiedAttributes::ACTIVE_CHECKS_ENABLED | ModifiedAttributes::CUSTOM_VARIABLE;
+        let json = serde_json::to_string(&attributes).unwrap();
+        assert_eq!(
+            json,
+            r#"["MODATTR_ACTIVE_CHECKS_ENABLED","MODATTR_CUSTOM_VARIABLE"]"#
+        );
+        assert_eq!(
+            serde_json::from_str::<ModifiedAttributes>(&json).unwrap(),
+            attributes
+        );
+        assert!(serde_json::from_str::<CheckOptions>(r#"["CHECK_OPTION_BOGUS"]"#).is_err());
+        assert!(serde_json::from_str::<CheckOptions>(r#"["0xzz"]"#).is_err());
+
+        // unknown bits are kept
+        struct TestCase(u32, &'static str);
+        let test_cases = vec![
+            TestCase(0, "[]"),
+            TestCase(1 << 20, r#"["0x100000"]"#),
+            TestCase(
+                (1 << 9) | (1 << 17) | (1 << 31),
+                r#"["MODATTR_CHECK_COMMAND","0x80020000"]"#,
+            ),
+        ];
+        for test_case in test_cases {
+            let attributes = ModifiedAttributes::from(test_case.0);
+            let json = serde_json::to_string(&attributes).unwrap();
+            assert_eq!(json, test_case.1);
+            assert_eq!(
+                serde_json::from_str::<ModifiedAttributes>(&json).unwrap(),
+                attributes
+            );
         }
+        let options = CheckOptions::from(CheckOptions::ORPHAN_CHECK.bits() | 1 << 8);
+        let json = serde_json::to_string(&options).unwrap();
+        assert_eq!(json, r#"["CHECK_OPTION_ORPHAN_CHECK","0x100"]"#);
+        assert_eq!(
+            serde_json::from_str::<CheckOptions>(&json).unwrap(),
+            options
+        );
     }
 
     #[test]
@@ -0,0 +1,252 @@
+use serde::Serialize;
+use std::fmt;
+
+use super::object::{Host, Service};
+
+const STATUS_WORDS: &[&str] = &[
+    "OK",
+    "WARNING",
+    "CRITICAL",
+    "UNKNOWN",
+    "UP",
+    "DOWN",
+    "UNREACHABLE",
+];
+
+/// Plugin output split into its parts, e.g. `PING OK - Packet loss = 0%`
+/// followed by the lines of the long output.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PluginOutput {
+    /// Word before the status, e.g. `PING` or `HTTP`.
+    pub label: Option<String>,
+    /// Leading status word, e.g. `OK` or `CRITICAL`.
+    pub status: Option<String>,
+    /// Rest of the first line after the status word and its `-`/`:` separator.
+    pub summary: String,
+    /// Unescaped lines of `long_plugin_output`.
+    pub body: Vec<String>,
+}
+
+impl PluginOutput {
+    pub fn parse(plugin_output: &str, long_plugin_output: &str) -> PluginOutput {
+        let (label, status, summary) = split_status(plugin_output.trim());
+        let long_output = unescape(long_plugin_output);
+        let body = long_output
+            .trim_end_matches('\n')
+            .lines()
+            .map(|line| line.to_string())
+            .collect::<Vec<_>>();
+        PluginOutput {
+            label: label.map(|label| label.to_string()),
+            status: status.map(|status| status.to_string()),
+            summary: summary.to_string(),
+            body: if body.iter().all(|line| line.is_empty()) {
+                Vec::new()
+            } else {
+                body
+            },
+        }
+    }
+}
+
+impl fmt::Display for PluginOutput {
+    /// `[LABEL ]STATUS: summary` followed by the body, one line each.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let (Some(label), Some(_)) = (&self.label, &self.status) {
+            write!(f, "{} ", label)?;
+        }
+        match &self.status {
+            Some(status) if self.summary.is_empty() => write!(f, "{}", status)?,
+            Some(status) => write!(f, "{}: {}", status, self.summary)?,
+            None => write!(f, "{}", self.summary)?,
+        }
+        for line in &self.body {
+            write!(f, "\n{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+/// Reverses the escaping Nagios applies to `long_plugin_output`: `\n` becomes
+/// a newline and `\\` a backslash. Other sequences are left as they are.
+pub fn unescape(s: &str) -> String {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('\\') => unescaped.push('\\'),
+            Some(other) => {
+                unescaped.push('\\');
+                unescaped.push(other);
+            }
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+/// Splits `[LABEL ]STATUS[:| -] summary`. The status word may be preceded by
+/// at most one label word.
+fn split_status(line: &str) -> (Option<&str>, Option<&str>, &str) {
+    let (first, after_first) = split_first_word(line);
+    if let Some((status, summary)) = match_status(first, after_first) {
+        return (None, Some(status), summary);
+    }
+    let (second, after_second) = split_first_word(after_first);
+    if let Some((status, summary)) = match_status(second, after_second) {
+        return (Some(first), Some(status), summary);
+    }
+    (None, None, line)
+}
+
+fn split_first_word(s: &str) -> (&str, &str) {
+    match s.find(char::is_whitespace) {
+        Some(i) => (&s[..i], s[i..].trim_start()),
+        None => (s, ""),
+    }
+}
+
+fn match_status<'a>(word: &'a str, rest: &'a str) -> Option<(&'a str, &'a str)> {
+    let status = word.strip_suffix(':').unwrap_or(word);
+    if !STATUS_WORDS.contains(&status) {
+        return None;
+    }
+    let summary = if status.len() < word.len() {
+        rest
+    } else {
+        rest.strip_prefix(['-', ':'])
+            .map_or(rest, |summary| summary.trim_start())
+    };
+    Some((status, summary))
+}
+
+impl Host {
+    pub fn output(&self) -> PluginOutput {
+        PluginOutput::parse(&self.plugin_output, &self.long_plugin_output)
+    }
+}
+
+impl Service {
+    pub fn output(&self) -> PluginOutput {
+        PluginOutput::parse(&self.plugin_output, &self.long_plugin_output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unescape() {
+        struct TestCase<'a>(&'a str, &'a str);
+        let test_cases = vec![
+            TestCase("", ""),
+            TestCase("line1\\nline2", "line1\nline2"),
+            TestCase("C:\\\\temp", "C:\\temp"),
+            TestCase("a\\\\nb", "a\\nb"),
+            TestCase("a\\tb", "a\\tb"),
+            TestCase("trailing\\", "trailing\\"),
+        ];
+        for test_case in test_cases {
+            assert_eq!(unescape(test_case.0), test_case.1);
+        }
+    }
+
+    #[test]
+    fn test_split_status() {
+        struct TestCase<'a>(&'a str, Option<&'a str>, Option<&'a str>, &'a str);
+        let test_cases = vec![
+            TestCase(
+                "OK - load average: 0.04, 0.03, 0.00",
+                None,
+                Some("OK"),
+                "load average: 0.04, 0.03, 0.00",
+            ),
+            TestCase("CRITICAL: disk full", None, Some("CRITICAL"), "disk full"),
+            TestCase(
+                "PING OK - Packet loss = 0%, RTA = 0.04 ms",
+                Some("PING"),
+                Some("OK"),
+                "Packet loss = 0%, RTA = 0.04 ms",
+            ),
+            TestCase(
+                "HTTP WARNING: HTTP/1.1 403 Forbidden",
+                Some("HTTP"),
+                Some("WARNING"),
+                "HTTP/1.1 403 Forbidden",
+            ),
+            TestCase("UNKNOWN", None, Some("UNKNOWN"), ""),
+            TestCase("All good", None, None, "All good"),
+            TestCase("Disk is OK", None, None, "Disk is OK"),
+            TestCase("", None, None, ""),
+        ];
+        for test_case in test_cases {
+            assert_eq!(
+                split_status(test_case.0),
+                (test_case.1, test_case.2, test_case.3),
+                "{}",
+                test_case.0
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let output = PluginOutput::parse(
+            "DISK CRITICAL - free space: / 3 MB (1%)",
+            "/ 3 MB (1%)\\n/boot 120 MB (60%)\\n",
+        );
+        assert_eq!(output.label.as_deref(), Some("DISK"));
+        assert_eq!(output.status.as_deref(), Some("CRITICAL"));
+        assert_eq!(output.body, vec!["/ 3 MB (1%)", "/boot 120 MB (60%)"]);
+        assert_eq!(
+            output.to_string(),
+            "DISK CRITICAL: free space: / 3 MB (1%)\n/ 3 MB (1%)\n/boot 120 MB (60%)"
+        );
+
+        assert!(PluginOutput::parse("OK", "").body.is_empty());
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        let test_cases = vec![
+            (
+                "DISK CRITICAL - free space: / 3 MB (1%)",
+                "/ 3 MB (1%)\\n/boot\\\\ 120 MB\\n",
+            ),
+            ("PING OK - Packet loss = 0%", ""),
+            ("HTTP WARNING", ""),
+            ("CRITICAL: connection refused", "retrying\\n"),
+            ("UNKNOWN", ""),
+            ("no status word here", ""),
+        ];
+        for (plugin_output, long_plugin_output) in test_cases {
+            let output = PluginOutput::parse(plugin_output, long_plugin_output);
+            let displayed = output.to_string();
+            let (first, rest) = displayed.split_once('\n').unwrap_or((&displayed, ""));
+            let escaped = rest.replace('\\', "\\\\").replace('\n', "\\n");
+            assert_eq!(
+                PluginOutput::parse(first, &escaped),
+                output,
+                "{}",
+                plugin_output
+            );
+        }
+    }
+
+    #[test]
+    fn test_host_output() {
+        let status = crate::nagios::NagiosStatus::parse_file("testdata/status.dat").unwrap();
+        let output = status.get_host("localhost").unwrap().output();
+        assert_eq!(output.label.as_deref(), Some("PING"));
+        assert_eq!(
+            output.to_string(),
+            "PING OK: Packet loss = 0%, RTA = 0.04 ms"
+        );
+    }
+}
@@ -0,0 +1,654 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+use super::object::{Host, Service, ServiceState};
+
+////////////////////////////////////
+// error
+
+#[derive(Error, Debug, PartialEq)]
+pub enum PerfDataError {
+    #[error("unterminated quoted label: {0}")]
+    UnterminatedLabel(String),
+    #[error("missing '=' in perfdata: {0}")]
+    MissingValue(String),
+    #[error("invalid perfdata value: {0}")]
+    InvalidValue(String),
+    #[error("invalid threshold range: {0}")]
+    InvalidRange(String),
+}
+
+////////////////////////////////////
+// unit
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Unit {
+    None,
+    Seconds,
+    Milliseconds,
+    Microseconds,
+    Percent,
+    Bytes,
+    Kilobytes,
+    Megabytes,
+    Gigabytes,
+    Terabytes,
+    Counter,
+    Other(String),
+}
+
+impl From<&str> for Unit {
+    fn from(s: &str) -> Self {
+        match s {
+            "" => Unit::None,
+            "s" => Unit::Seconds,
+            "ms" => Unit::Milliseconds,
+            "us" => Unit::Microseconds,
+            "%" => Unit::Percent,
+            "B" => Unit::Bytes,
+            "KB" => Unit::Kilobytes,
+            "MB" => Unit::Megabytes,
+            "GB" => Unit::Gigabytes,
+            "TB" => Unit::Terabytes,
+            "c" => Unit::Counter,
+            s => Unit::Other(s.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Unit::None => "",
+            Unit::Seconds => "s",
+            Unit::Milliseconds => "ms",
+            Unit::Microseconds => "us",
+            Unit::Percent => "%",
+            Unit::Bytes => "B",
+            Unit::Kilobytes => "KB",
+            Unit::Megabytes => "MB",
+            Unit::Gigabytes => "GB",
+            Unit::Terabytes => "TB",
+            Unit::Counter => "c",
+            Unit::Other(s) => s,
+        };
+        write!(f, "{}", s)
+    }
+}
+
+////////////////////////////////////
+// threshold range
+
+/// A warn/crit range in the plugin guidelines syntax: `10`, `10:`, `~:10`,
+/// `10:20` or `@10:20`. `~` and an omitted end are stored as infinities.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ThresholdRange {
+    pub start: f64,
+    pub end: f64,
+    /// `@` prefix: alert when the value is inside the range instead of outside.
+    pub inside: bool,
+}
+
+impl ThresholdRange {
+    /// Whether `value` should raise an alert: outside `start..=end`, or
+    /// inside it for `@` ranges.
+    pub fn is_alert(&self, value: f64) -> bool {
+        let within = self.start <= value && value <= self.end;
+        within == self.inside
+    }
+}
+
+impl FromStr for ThresholdRange {
+    type Err = PerfDataError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || PerfDataError::InvalidRange(s.to_string());
+        let (inside, range) = match s.strip_prefix('@') {
+            Some(range) => (true, range),
+            None => (false, s),
+        };
+
+        let (start, end) = match range.split_once(':') {
+            None => (0.0, parse_number(range).ok_or_else(invalid)?),
+            Some((start, end)) => {
+                let start = match start {
+                    "~" => f64::NEG_INFINITY,
+                    "" => 0.0,
+                    start => parse_number(start).ok_or_else(invalid)?,
+                };
+                let end = match end {
+                    "" => f64::INFINITY,
+                    end => parse_number(end).ok_or_else(invalid)?,
+                };
+                (start, end)
+            }
+        };
+        if start > end {
+            return Err(invalid());
+        }
+
+        Ok(ThresholdRange { start, end, inside })
+    }
+}
+
+impl fmt::Display for ThresholdRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.inside {
+            write!(f, "@")?;
+        }
+        match (self.start, self.end) {
+            (start, end) if start == 0.0 && end.is_finite() => write!(f, "{}", end),
+            (start, end) => {
+                if start == f64::NEG_INFINITY {
+                    write!(f, "~:")?;
+                } else {
+                    write!(f, "{}:", start)?;
+                }
+                if end.is_finite() {
+                    write!(f, "{}", end)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+fn parse_number(s: &str) -> Option<f64> {
+    s.parse::<f64>().ok().filter(|n| n.is_finite())
+}
+
+////////////////////////////////////
+// perf datum
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PerfDatum {
+    pub label: String,
+    /// `None` when the plugin reported `U` (value could not be determined).
+    pub value: Option<f64>,
+    pub unit: Unit,
+    pub warn: Option<ThresholdRange>,
+    pub crit: Option<ThresholdRange>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+impl PerfDatum {
+    /// State implied by the datum's own thresholds: `Critical` if the crit
+    /// range alerts, `Warning` if the warn range alerts, `Ok` otherwise.
+    /// `None` when the value is `U` or the datum has no thresholds.
+    pub fn state(&self) -> Option<ServiceState> {
+        let value = self.value?;
+        if self.warn.is_none() && self.crit.is_none() {
+            return None;
+        }
+        let alerts = |range: &Option<ThresholdRange>| {
+            range.as_ref().is_some_and(|range| range.is_alert(value))
+        };
+        if alerts(&self.crit) {
+            Some(ServiceState::Critical)
+        } else if alerts(&self.warn) {
+            Some(ServiceState::Warning)
+        } else {
+            Some(ServiceState::Ok)
+        }
+    }
+}
+
+/// Worst state implied by `data`, or `None` if no datum has thresholds.
+pub fn expected_state(data: &[PerfDatum]) -> Option<ServiceState> {
+    let severity = |state: &ServiceState| match state {
+        ServiceState::Ok => 0,
+        ServiceState::Warning => 1,
+        ServiceState::Unknown => 2,
+        ServiceState::Critical => 3,
+    };
+    data.iter()
+        .filter_map(|datum| datum.state())
+        .max_by_key(severity)
+}
+
+/// Parses perfdata following the plugin guidelines strictly.
+pub fn parse(s: &str) -> Result<Vec<PerfDatum>, PerfDataError> {
+    let (tokens, error) = tokenize(s);
+    if let Some(error) = error {
+        return Err(error);
+    }
+    tokens
+        .into_iter()
+        .map(|(label, rest)| match rest {
+            Some(rest) => parse_datum(label, &rest, false),
+            None => Err(PerfDataError::MissingValue(label)),
+        })
+        .collect()
+}
+
+/// Parses perfdata from plugins that do not quite follow the guidelines.
+///
+/// Unquoted labels containing spaces, `,` as decimal separator, units on
+/// min/max and extra fields are accepted; invalid thresholds are dropped and
+/// datums that still cannot be parsed are skipped.
+pub fn parse_tolerant(s: &str) -> Vec<PerfDatum> {
+    // an unterminated quote swallows the rest of the string
+    let (tokens, _) = tokenize(s);
+
+    let mut data = Vec::new();
+    let mut label_prefix: Vec<String> = Vec::new();
+    for (label, rest) in tokens {
+        match rest {
+            None => label_prefix.push(label),
+            Some(rest) => {
+                label_prefix.push(label);
+                let label = label_prefix.join(" ");
+                label_prefix.clear();
+                if let Ok(datum) = parse_datum(label, &rest, true) {
+                    data.push(datum);
+                }
+            }
+        }
+    }
+    data
+}
+
+type Token = (String, Option<String>);
+
+/// Splits perfdata into `(label, value part)` pairs. The value part is `None`
+/// for tokens without `=`. Tokenizing stops at an unterminated quoted label,
+/// returning the tokens before it together with the error.
+fn tokenize(s: &str) -> (Vec<Token>, Option<PerfDataError>) {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let first = match chars.peek() {
+            Some(c) => *c,
+            None => return (tokens, None),
+        };
+
+        if first == '\'' {
+            chars.next();
+            let mut label = String::new();
+            loop {
+                match chars.next() {
+                    Some('\'') if chars.peek() == Some(&'\'') => {
+                        chars.next();
+                        label.push('\'');
+                    }
+                    Some('\'') => break,
+                    Some(c) => label.push(c),
+                    None => return (tokens, Some(PerfDataError::UnterminatedLabel(label))),
+                }
+            }
+            let mut rest = String::new();
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                rest.push(c);
+            }
+            match rest.strip_prefix('=') {
+                Some(rest) => tokens.push((label, Some(rest.to_string()))),
+                None => tokens.push((label, None)),
+            }
+        } else {
+            let mut token = String::new();
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                token.push(c);
+            }
+            match token.split_once('=') {
+                Some((label, rest)) => tokens.push((label.to_string(), Some(rest.to_string()))),
+                None => tokens.push((token, None)),
+            }
+        }
+    }
+}
+
+fn parse_datum(label: String, rest: &str, tolerant: bool) -> Result<PerfDatum, PerfDataError> {
+    let mut fields = rest.split(';');
+    let value_field = fields.next().unwrap_or_default();
+    let (value, unit) = if value_field == "U" {
+        (None, Unit::None)
+    } else {
+        let (number, unit) = split_unit(value_field);
+        let value = parse_field_number(number, tolerant)
+            .ok_or_else(|| PerfDataError::InvalidValue(value_field.to_string()))?;
+        (Some(value), Unit::from(unit))
+    };
+
+    let range = |field: Option<&str>| -> Result<Option<ThresholdRange>, PerfDataError> {
+        match field {
+            None | Some("") => Ok(None),
+            Some(field) if tolerant => Ok(field.replace(',', ".").parse().ok()),
+            Some(field) => field.parse().map(Some),
+        }
+    };
+    let warn = range(fields.next())?;
+    let crit = range(fields.next())?;
+
+    let number = |field: Option<&str>| -> Result<Option<f64>, PerfDataError> {
+        match field {
+            None | Some("") => Ok(None),
+            Some(field) if tolerant => Ok(parse_field_number(split_unit(field).0, true)),
+            Some(field) => parse_number(field)
+                .map(Some)
+                .ok_or_else(|| PerfDataError::InvalidValue(field.to_string())),
+        }
+    };
+    let min = number(fields.next())?;
+    let max = number(fields.next())?;
+
+    if !tolerant && fields.next().is_some() {
+        return Err(PerfDataError::InvalidValue(rest.to_string()));
+    }
+
+    Ok(PerfDatum {
+        label,
+        value,
+        unit,
+        warn,
+        crit,
+        min,
+        max,
+    })
+}
+
+/// Splits `12.5ms` into `("12.5", "ms")` and `1.5e-3s` into `("1.5e-3", "s")`.
+/// Decimal commas stay in the number, for the tolerant parser to replace.
+fn split_unit(s: &str) -> (&str, &str) {
+    let bytes = s.as_bytes();
+    let sign = |i: usize| usize::from(matches!(bytes.get(i), Some(b'-' | b'+')));
+    let digits = |i: usize| bytes[i..].iter().take_while(|b| b.is_ascii_digit()).count();
+
+    let mut index = sign(0);
+    index += bytes[index..]
+        .iter()
+        .take_while(|b| b.is_ascii_digit() || matches!(b, b'.' | b','))
+        .count();
+    // an exponent only if the mantissa has a digit and the exponent has one
+    if bytes[..index].iter().any(u8::is_ascii_digit)
+        && matches!(bytes.get(index), Some(b'e' | b'E'))
+    {
+        let exponent = index + 1 + sign(index + 1);
+        let exponent_digits = digits(exponent);
+        if exponent_digits > 0 {
+            index = exponent + exponent_digits;
+        }
+    }
+    s.split_at(index)
+}
+
+fn parse_field_number(s: &str, tolerant: bool) -> Option<f64> {
+    if tolerant {
+        parse_number(&s.replace(',', "."))
+    } else {
+        parse_number(s)
+    }
+}
+
+impl Host {
+    pub fn perf_data(&self) -> Result<Vec<PerfDatum>, PerfDataError> {
+        parse(&self.performance_data)
+    }
+
+    pub fn perf_data_tolerant(&self) -> Vec<PerfDatum> {
+        parse_tolerant(&self.performance_data)
+    }
+}
+
+impl Service {
+    pub fn perf_data(&self) -> Result<Vec<PerfDatum>, PerfDataError> {
+        parse(&self.performance_data)
+    }
+
+    pub fn perf_data_tolerant(&self) -> Vec<PerfDatum> {
+        parse_tolerant(&self.performance_data)
+    }
+
+    /// State the service's own perfdata thresholds imply.
+    pub fn perf_data_state(&self) -> Option<ServiceState> {
+        expected_state(&self.perf_data_tolerant())
+    }
+
+    /// Whether the reported `current_state` differs from the state implied by
+    /// the perfdata thresholds. Services without thresholds never disagree.
+    pub fn disagrees_with_perf_data(&self) -> bool {
+        self.perf_data_state()
+            .is_some_and(|state| state != self.current_state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(start: f64, end: f64, inside: bool) -> ThresholdRange {
+        ThresholdRange { start, end, inside }
+    }
+
+    #[test]
+    fn test_threshold_range_from_str() {
+        struct TestCase<'a>(&'a str, Result<ThresholdRange, PerfDataError>);
+        let test_cases = vec![
+            TestCase("10", Ok(range(0.0, 10.0, false))),
+            TestCase("10:", Ok(range(10.0, f64::INFINITY, false))),
+            TestCase("~:10", Ok(range(f64::NEG_INFINITY, 10.0, false))),
+            TestCase("10:20", Ok(range(10.0, 20.0, false))),
+            TestCase("@10:20", Ok(range(10.0, 20.0, true))),
+            TestCase("-5.5:-1", Ok(range(-5.5, -1.0, false))),
+            TestCase("20:10", Err(PerfDataError::InvalidRange("20:10".into()))),
+            TestCase("hoge", Err(PerfDataError::InvalidRange("hoge".into()))),
+        ];
+        for test_case in test_cases {
+            assert_eq!(test_case.0.parse::<ThresholdRange>(), test_case.1);
+        }
+    }
+
+    #[test]
+    fn test_threshold_range_display() {
+        for s in ["10", "10:", "~:10", "10:20", "@10:20", "~:"] {
+            assert_eq!(s.parse::<ThresholdRange>().unwrap().to_string(), s);
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let data = parse("rta=0.041000ms;3000.000000;5000.000000;0.000000 pl=0%;80;100;0").unwrap();
+        assert_eq!(
+            data,
+            vec![
+                PerfDatum {
+                    label: "rta".into(),
+                    value: Some(0.041),
+                    unit: Unit::Milliseconds,
+                    warn: Some(range(0.0, 3000.0, false)),
+                    crit: Some(range(0.0, 5000.0, false)),
+                    min: Some(0.0),
+                    max: None,
+                },
+                PerfDatum {
+                    label: "pl".into(),
+                    value: Some(0.0),
+                    unit: Unit::Percent,
+                    warn: Some(range(0.0, 80.0, false)),
+                    crit: Some(range(0.0, 100.0, false)),
+                    min: Some(0.0),
+                    max: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_labels_and_values() {
+        struct TestCase<'a>(&'a str, &'a str, Option<f64>, Unit);
+        let test_cases = vec![
+            TestCase(
+                "load1=0.040;5.000;10.000;0;",
+                "load1",
+                Some(0.04),
+                Unit::None,
+            ),
+            TestCase(
+                "'/ used'=1024MB;;;0;2048",
+                "/ used",
+                Some(1024.0),
+                Unit::Megabytes,
+            ),
+            TestCase("'it''s'=1c", "it's", Some(1.0), Unit::Counter),
+            TestCase("time=U;1;2", "time", None, Unit::None),
+            TestCase("temp=-3.5", "temp", Some(-3.5), Unit::None),
+            TestCase("mem=12KiB", "mem", Some(12.0), Unit::Other("KiB".into())),
+            TestCase("time=1.5e-3s", "time", Some(0.0015), Unit::Seconds),
+            TestCase("size=2E+6B", "size", Some(2e6), Unit::Bytes),
+            TestCase("rate=5e3", "rate", Some(5e3), Unit::None),
+            // without exponent digits the `e` starts the unit
+            TestCase("events=5ev", "events", Some(5.0), Unit::Other("ev".into())),
+        ];
+        for test_case in test_cases {
+            let data = parse(test_case.0).unwrap();
+            assert_eq!(data.len(), 1);
+            assert_eq!(data[0].label, test_case.1);
+            assert_eq!(data[0].value, test_case.2);
+            assert_eq!(data[0].unit, test_case.3);
+        }
+    }
+
+    #[test]
+    fn test_parse_error() {
+        struct TestCase<'a>(&'a str, PerfDataError);
+        let test_cases = vec![
+            TestCase(
+                "'label=1",
+                PerfDataError::UnterminatedLabel("label=1".into()),
+            ),
+            TestCase("label", PerfDataError::MissingValue("label".into())),
+            TestCase("label=abc", PerfDataError::InvalidValue("abc".into())),
+            TestCase("label=1;x", PerfDataError::InvalidRange("x".into())),
+            TestCase("label=1,5", PerfDataError::InvalidValue("1,5".into())),
+        ];
+        for test_case in test_cases {
+            assert_eq!(parse(test_case.0), Err(test_case.1));
+        }
+    }
+
+    #[test]
+    fn test_parse_tolerant() {
+        let data = parse_tolerant("Disk C: Used=1,5GB;x;90;0GB;100GB broken ok=1;;;;;extra 'bad");
+        assert_eq!(
+            data,
+            vec![
+                PerfDatum {
+                    label: "Disk C: Used".into(),
+                    value: Some(1.5),
+                    unit: Unit::Gigabytes,
+                    warn: None,
+                    crit: Some(range(0.0, 90.0, false)),
+                    min: Some(0.0),
+                    max: Some(100.0),
+                },
+                PerfDatum {
+                    label: "broken ok".into(),
+                    value: Some(1.0),
+                    unit: Unit::None,
+                    warn: None,
+                    crit: None,
+                    min: None,
+                    max: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_testdata() {
+        let status = crate::nagios::NagiosStatus::parse_file("testdata/status.dat").unwrap();
+        let host = status.get_host("localhost").unwrap();
+        assert_eq!(host.perf_data().unwrap().len(), 2);
+        for service in status.get_host_services("localhost").unwrap() {
+            assert!(service.perf_data().is_ok(), "{}", service.performance_data);
+        }
+    }
+
+    #[test]
+    fn test_is_alert() {
+        struct TestCase<'a>(&'a str, f64, bool);
+        let test_cases = vec![
+            // 10: alert if < 0 or > 10
+            TestCase("10", -1.0, true),
+            TestCase("10", 0.0, false),
+            TestCase("10", 10.0, false),
+            TestCase("10", 10.1, true),
+            // 10: alert if < 10
+            TestCase("10:", 9.9, true),
+            TestCase("10:", 1e9, false),
+            // ~:10 alert if > 10
+            TestCase("~:10", -1e9, false),
+            TestCase("~:10", 11.0, true),
+            // 10:20 alert if < 10 or > 20
+            TestCase("10:20", 9.0, true),
+            TestCase("10:20", 15.0, false),
+            TestCase("10:20", 21.0, true),
+            // @10:20 alert if >= 10 and <= 20
+            TestCase("@10:20", 9.0, false),
+            TestCase("@10:20", 10.0, true),
+            TestCase("@10:20", 20.0, true),
+            TestCase("@10:20", 21.0, false),
+        ];
+        for test_case in test_cases {
+            let range: ThresholdRange = test_case.0.parse().unwrap();
+            assert_eq!(
+                range.is_alert(test_case.1),
+                test_case.2,
+                "{} {}",
+                test_case.0,
+                test_case.1
+            );
+        }
+    }
+
+    #[test]
+    fn test_datum_state() {
+        struct TestCase<'a>(&'a str, Option<ServiceState>);
+        let test_cases = vec![
+            TestCase("load=1;5;10", Some(ServiceState::Ok)),
+            TestCase("load=6;5;10", Some(ServiceState::Warning)),
+            TestCase("load=11;5;10", Some(ServiceState::Critical)),
+            TestCase("free=5;10:;~:", Some(ServiceState::Warning)),
+            TestCase("load=11;;10", Some(ServiceState::Critical)),
+            TestCase("load=11", None),
+            TestCase("load=U;5;10", None),
+        ];
+        for test_case in test_cases {
+            assert_eq!(parse(test_case.0).unwrap()[0].state(), test_case.1);
+        }
+    }
+
+    #[test]
+    fn test_expected_state() {
+        struct TestCase<'a>(&'a str, Option<ServiceState>);
+        let test_cases = vec![
+            TestCase("", None),
+            TestCase("a=1 b=2", None),
+            TestCase("a=1;5;10 b=2", Some(ServiceState::Ok)),
+            TestCase("a=6;5;10 b=11;5;10 c=1;5;10", Some(ServiceState::Critical)),
+            TestCase("a=6;5;10 b=1;5;10", Some(ServiceState::Warning)),
+        ];
+        for test_case in test_cases {
+            assert_eq!(expected_state(&parse(test_case.0).unwrap()), test_case.1);
+        }
+    }
+
+    #[test]
+    fn test_disagrees_with_perf_data() {
+        let status = crate::nagios::NagiosStatus::parse_file("testdata/status.dat").unwrap();
+        let mut service = status
+            .get_host_services("localhost")
+            .unwrap()
+            .into_iter()
+            .find(|service| service.service_description == "Current Load")
+            .unwrap();
+        assert_eq!(service.perf_data_state(), Some(ServiceState::Ok));
+        assert!(!service.disagrees_with_perf_data());
+
+        service.current_state = ServiceState::Critical;
+        assert!(service.disagrees_with_perf_data());
+    }
+}
@@ -0,0 +1,385 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::{FileTypeExt, OpenOptionsExt};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+use super::cmd::{self, NagiosCmd};
+
+pub const DEFAULT_WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Writes of at most this many bytes to a pipe are atomic.
+pub const PIPE_BUF: usize = libc::PIPE_BUF;
+
+////////////////////////////////////
+// error
+
+#[derive(Error, Debug)]
+pub enum PipeError {
+    #[error("command file does not exist: {0}")]
+    NotFound(PathBuf),
+    #[error("command file is not a named pipe: {0}")]
+    NotAFifo(PathBuf),
+    #[error("nagios is not running (no reader on {0})")]
+    NagiosNotRunning(PathBuf),
+    #[error("timed out writing to command file after {0:?}")]
+    Timeout(Duration),
+    #[error("command line is {0} bytes, longer than PIPE_BUF")]
+    CommandTooLong(usize),
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+}
+
+#[derive(Error, Debug)]
+#[error("{error} ({written} of {total} commands written)")]
+pub struct BatchWriteError {
+    /// Number of commands, from the start of the batch, that were written.
+    pub written: usize,
+    pub total: usize,
+    #[source]
+    pub error: PipeError,
+}
+
+////////////////////////////////////
+// command pipe
+
+/// Writer for the Nagios external command file (a named pipe).
+///
+/// The pipe is opened with `O_NONBLOCK` so that a missing reader is reported
+/// immediately instead of blocking forever, and every write waits at most
+/// `timeout` for the pipe to drain.
+#[derive(Debug, Clone)]
+pub struct CommandPipe {
+    path: PathBuf,
+    timeout: Duration,
+}
+
+impl CommandPipe {
+    pub fn new<P: AsRef<Path>>(path: P) -> CommandPipe {
+        CommandPipe {
+            path: path.as_ref().to_path_buf(),
+            timeout: DEFAULT_WRITE_TIMEOUT,
+        }
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> CommandPipe {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Writes `cmds` to the pipe, packing whole lines into chunks of at most
+    /// `PIPE_BUF` bytes and issuing one `write(2)` per chunk so that lines
+    /// never interleave with other writers.
+    ///
+    /// On failure the returned error reports how many of `cmds` (counted from
+    /// the start) were written before the error occurred.
+    pub fn write_cmds(
+        &self,
+        cmds: &[Box<dyn NagiosCmd>],
+        timestamp: i64,
+    ) -> Result<(), BatchWriteError> {
+        let batch_error = |written: usize, error: PipeError| BatchWriteError {
+            written,
+            total: cmds.len(),
+            error,
+        };
+
+        let lines = cmds
+            .iter()
+            .map(|cmd| {
+                let mut line = Vec::new();
+                cmd::write_cmd_line(cmd.as_ref(), timestamp, &mut line)?;
+                if line.len() > PIPE_BUF {
+                    return Err(PipeError::CommandTooLong(line.len()));
+                }
+                Ok(line)
+            })
+            .collect::<Result<Vec<_>, PipeError>>()
+            .map_err(|error| batch_error(0, error))?;
+
+        let mut writer = self.open().map_err(|error| batch_error(0, error))?;
+        let mut written = 0;
+        for (chunk, count) in chunks(&lines) {
+            writer
+                .write_atomic(&chunk)
+                .map_err(|error| batch_error(written, self.map_io_error(error)))?;
+            written += count;
+        }
+        Ok(())
+    }
+
+    fn open(&self) -> Result<TimeoutWriter, PipeError> {
+        let metadata = std::fs::metadata(&self.path).map_err(|error| match error.kind() {
+            io::ErrorKind::NotFound => PipeError::NotFound(self.path.clone()),
+            _ => PipeError::Io(error),
+        })?;
+        if !metadata.file_type().is_fifo() {
+            return Err(PipeError::NotAFifo(self.path.clone()));
+        }
+
+        let file = OpenOptions::new()
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(&self.path)
+            .map_err(|error| self.map_io_error(error))?;
+
+        Ok(TimeoutWriter {
+            file,
+            deadline: Instant::now() + self.timeout,
+        })
+    }
+
+    fn map_io_error(&self, error: io::Error) -> PipeError {
+        match error.raw_os_error() {
+            // open(2) on a FIFO with O_NONBLOCK and no reader
+            Some(libc::ENXIO) => return PipeError::NagiosNotRunning(self.path.clone()),
+            // the reader went away while we were writing
+            Some(libc::EPIPE) => return PipeError::NagiosNotRunning(self.path.clone()),
+            _ => {}
+        }
+        match error.kind() {
+            io::ErrorKind::TimedOut => PipeError::Timeout(self.timeout),
+            _ => PipeError::Io(error),
+        }
+    }
+}
+
+struct TimeoutWriter {
+    file: File,
+    deadline: Instant,
+}
+
+impl TimeoutWriter {
+    fn wait_writable(&self) -> io::Result<()> {
+        loop {
+            let remaining = self.deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(io::Error::from(io::ErrorKind::TimedOut));
+            }
+
+            let mut pollfd = libc::pollfd {
+                fd: self.file.as_raw_fd(),
+                events: libc::POLLOUT,
+                revents: 0,
+            };
+            let timeout_ms =
+                remaining.as_millis().clamp(1, libc::c_int::MAX as u128) as libc::c_int;
+            match unsafe { libc::poll(&mut pollfd, 1, timeout_ms) } {
+                -1 => {
+                    let error = io::Error::last_os_error();
+                    if error.kind() != io::ErrorKind::Interrupted {
+                        return Err(error);
+                    }
+                }
+                0 => return Err(io::Error::from(io::ErrorKind::TimedOut)),
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    /// Writes `buf` with a single `write(2)`. `buf` must not exceed
+    /// `PIPE_BUF`, which makes the write all-or-nothing.
+    fn write_atomic(&mut self, buf: &[u8]) -> io::Result<()> {
+        loop {
+            match self.file.write(buf) {
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => self.wait_writable()?,
+                Err(error) => return Err(error),
+                Ok(n) if n == buf.len() => return Ok(()),
+                Ok(_) => return Err(io::Error::from(io::ErrorKind::WriteZero)),
+            }
+        }
+    }
+}
+
+/// Packs consecutive lines into chunks no larger than `PIPE_BUF`, returning
+/// each chunk together with the number of lines it contains.
+fn chunks(lines: &[Vec<u8>]) -> Vec<(Vec<u8>, usize)> {
+    let mut chunks = Vec::new();
+    let mut chunk: Vec<u8> = Vec::with_capacity(PIPE_BUF);
+    let mut count = 0;
+    for line in lines {
+        if chunk.len() + line.len() > PIPE_BUF {
+            chunks.push((std::mem::take(&mut chunk), count));
+            count = 0;
+        }
+        chunk.extend_from_slice(line);
+        count += 1;
+    }
+    if count > 0 {
+        chunks.push((chunk, count));
+    }
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nagios::cmd::DisableHostCheck;
+    use std::ffi::CString;
+    use std::io::Read;
+    use std::os::unix::ffi::OsStrExt;
+
+    fn mkfifo(path: &Path) {
+        let c_path = CString::new(path.as_os_str().as_bytes()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) }, 0);
+    }
+
+    fn open_reader(path: &Path) -> File {
+        OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(path)
+            .unwrap()
+    }
+
+    fn cmds(count: usize) -> Vec<Box<dyn NagiosCmd>> {
+        (0..count)
+            .map(|i| {
+                Box::new(DisableHostCheck {
+                    host_name: format!("web{:05}", i),
+                }) as Box<dyn NagiosCmd>
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_write_cmds() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nagios.cmd");
+        mkfifo(&path);
+        let mut reader = open_reader(&path);
+
+        let pipe = CommandPipe::new(&path);
+        pipe.write_cmds(&cmds(2), 1647824400).unwrap();
+
+        let mut buf = String::new();
+        reader.read_to_string(&mut buf).unwrap();
+        assert_eq!(
+            buf,
+            "[1647824400] DISABLE_HOST_CHECK;web00000\n[1647824400] DISABLE_HOST_CHECK;web00001\n"
+        );
+    }
+
+    #[test]
+    fn test_write_cmds_not_found() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nagios.cmd");
+
+        let result = CommandPipe::new(&path).write_cmds(&cmds(1), 0);
+        assert!(matches!(
+            result,
+            Err(BatchWriteError {
+                written: 0,
+                error: PipeError::NotFound(_),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_write_cmds_not_a_fifo() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nagios.cmd");
+        File::create(&path).unwrap();
+
+        let result = CommandPipe::new(&path).write_cmds(&cmds(1), 0);
+        assert!(matches!(
+            result,
+            Err(BatchWriteError {
+                written: 0,
+                error: PipeError::NotAFifo(_),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_write_cmds_nagios_not_running() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nagios.cmd");
+        mkfifo(&path);
+
+        let result = CommandPipe::new(&path).write_cmds(&cmds(1), 0);
+        assert!(matches!(
+            result,
+            Err(BatchWriteError {
+                written: 0,
+                error: PipeError::NagiosNotRunning(_),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_write_cmds_timeout() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nagios.cmd");
+        mkfifo(&path);
+        // keep the reader open but never read, so the pipe fills up
+        let mut reader = open_reader(&path);
+
+        let timeout = Duration::from_millis(50);
+        let result = CommandPipe::new(&path)
+            .with_timeout(timeout)
+            .write_cmds(&cmds(10000), 0);
+        let error = result.unwrap_err();
+        assert!(matches!(error.error, PipeError::Timeout(t) if t == timeout));
+        assert_eq!(error.total, 10000);
+
+        // exactly the reported commands reached the pipe, as whole lines
+        let mut buf = String::new();
+        reader.read_to_string(&mut buf).unwrap();
+        assert!(buf.ends_with('\n'));
+        assert_eq!(buf.lines().count(), error.written);
+        assert_eq!(
+            buf.lines().last().unwrap(),
+            format!("[0] DISABLE_HOST_CHECK;web{:05}", error.written - 1)
+        );
+    }
+
+    #[test]
+    fn test_write_cmds_too_long() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nagios.cmd");
+        mkfifo(&path);
+        let _reader = open_reader(&path);
+
+        let cmds: Vec<Box<dyn NagiosCmd>> = vec![Box::new(DisableHostCheck {
+            host_name: "x".repeat(PIPE_BUF),
+        })];
+        let result = CommandPipe::new(&path).write_cmds(&cmds, 0);
+        assert!(matches!(
+            result,
+            Err(BatchWriteError {
+                written: 0,
+                error: PipeError::CommandTooLong(_),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_chunks() {
+        struct TestCase(Vec<usize>, Vec<usize>);
+        let test_cases = vec![
+            TestCase(vec![], vec![]),
+            TestCase(vec![10, 20], vec![2]),
+            TestCase(vec![PIPE_BUF], vec![1]),
+            TestCase(vec![PIPE_BUF / 2, PIPE_BUF / 2], vec![2]),
+            TestCase(vec![PIPE_BUF / 2, PIPE_BUF / 2, 1], vec![2, 1]),
+            TestCase(vec![PIPE_BUF - 1, 2, PIPE_BUF], vec![1, 1, 1]),
+        ];
+
+        for test_case in test_cases {
+            let lines: Vec<Vec<u8>> = test_case.0.iter().map(|len| vec![b'a'; *len]).collect();
+            let chunks = chunks(&lines);
+            assert!(chunks.iter().all(|(chunk, _)| chunk.len() <= PIPE_BUF));
+            assert_eq!(
+                chunks.iter().map(|(_, count)| *count).collect::<Vec<_>>(),
+                test_case.1
+            );
+        }
+    }
+}
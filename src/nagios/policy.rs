@@ -0,0 +1,349 @@
+use regex::Regex;
+use std::collections::VecDeque;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+use super::cmd::NagiosCmd;
+
+////////////////////////////////////
+// error
+
+#[derive(Error, Debug, PartialEq)]
+pub enum PolicyError {
+    #[error("command denied by policy: {0}")]
+    Denied(String),
+    #[error("rate limit exceeded: at most {max} commands matching {pattern} per {window:?}")]
+    RateLimited {
+        pattern: String,
+        max: usize,
+        window: Duration,
+    },
+}
+
+////////////////////////////////////
+// rule
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Decision {
+    Allow,
+    Deny,
+}
+
+/// Matches commands by name and target. Every pattern that is set must match;
+/// `hosts` and `hostgroups` only match commands that take a `host_name` or
+/// `hostgroup_name` argument respectively.
+#[derive(Debug, Clone, Default)]
+pub struct Rule {
+    commands: Option<Regex>,
+    hosts: Option<Regex>,
+    hostgroups: Option<Regex>,
+}
+
+impl Rule {
+    pub fn new() -> Rule {
+        Rule::default()
+    }
+
+    pub fn commands(mut self, re: Regex) -> Rule {
+        self.commands = Some(re);
+        self
+    }
+
+    pub fn hosts(mut self, re: Regex) -> Rule {
+        self.hosts = Some(re);
+        self
+    }
+
+    pub fn hostgroups(mut self, re: Regex) -> Rule {
+        self.hostgroups = Some(re);
+        self
+    }
+
+    pub fn is_match(&self, cmd: &dyn NagiosCmd) -> bool {
+        let arg_matches = |re: &Option<Regex>, key: &str| match re {
+            Some(re) => cmd.arg(key).is_some_and(|value| re.is_match(&value)),
+            None => true,
+        };
+
+        self.commands
+            .as_ref()
+            .is_none_or(|re| re.is_match(cmd.name()))
+            && arg_matches(&self.hosts, "host_name")
+            && arg_matches(&self.hostgroups, "hostgroup_name")
+    }
+}
+
+////////////////////////////////////
+// rate limit
+
+#[derive(Debug)]
+struct RateLimit {
+    commands: Regex,
+    max: usize,
+    window: Duration,
+    history: Mutex<VecDeque<Instant>>,
+}
+
+impl RateLimit {
+    fn lock_history(&self, now: Instant) -> MutexGuard<'_, VecDeque<Instant>> {
+        let mut history = self
+            .history
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        while let Some(sent) = history.front() {
+            if now.duration_since(*sent) < self.window {
+                break;
+            }
+            history.pop_front();
+        }
+        history
+    }
+}
+
+////////////////////////////////////
+// policy
+
+/// Decides which commands `Nagrs` may write.
+///
+/// Rules are evaluated in order and the first matching rule decides; commands
+/// matching no rule get the default decision. Rate limits apply on top of the
+/// rules and are counted across calls.
+#[derive(Debug)]
+pub struct CommandPolicy {
+    rules: Vec<(Decision, Rule)>,
+    default: Decision,
+    rate_limits: Vec<RateLimit>,
+}
+
+impl CommandPolicy {
+    pub fn allow_by_default() -> CommandPolicy {
+        CommandPolicy {
+            rules: Vec::new(),
+            default: Decision::Allow,
+            rate_limits: Vec::new(),
+        }
+    }
+
+    pub fn deny_by_default() -> CommandPolicy {
+        CommandPolicy {
+            rules: Vec::new(),
+            default: Decision::Deny,
+            rate_limits: Vec::new(),
+        }
+    }
+
+    pub fn allow(mut self, rule: Rule) -> CommandPolicy {
+        self.rules.push((Decision::Allow, rule));
+        self
+    }
+
+    pub fn deny(mut self, rule: Rule) -> CommandPolicy {
+        self.rules.push((Decision::Deny, rule));
+        self
+    }
+
+    /// Allows at most `max` commands whose name matches `commands` within any
+    /// `window`, e.g. `^DISABLE_` 10 per minute.
+    pub fn rate_limit(mut self, commands: Regex, max: usize, window: Duration) -> CommandPolicy {
+        self.rate_limits.push(RateLimit {
+            commands,
+            max,
+            window,
+            history: Mutex::new(VecDeque::new()),
+        });
+        self
+    }
+
+    pub fn decide(&self, cmd: &dyn NagiosCmd) -> Decision {
+        self.rules
+            .iter()
+            .find(|(_, rule)| rule.is_match(cmd))
+            .map_or(self.default, |(decision, _)| *decision)
+    }
+
+    /// Checks the whole batch without counting it against the rate limits.
+    pub fn check(&self, cmds: &[Box<dyn NagiosCmd>]) -> Result<(), PolicyError> {
+        self.evaluate(cmds, false)
+    }
+
+    /// Checks the whole batch and, if it is admitted, counts it against the
+    /// rate limits. Either every command is admitted or none is.
+    pub fn admit(&self, cmds: &[Box<dyn NagiosCmd>]) -> Result<(), PolicyError> {
+        self.evaluate(cmds, true)
+    }
+
+    fn evaluate(&self, cmds: &[Box<dyn NagiosCmd>], record: bool) -> Result<(), PolicyError> {
+        if let Some(cmd) = cmds
+            .iter()
+            .find(|cmd| self.decide(cmd.as_ref()) == Decision::Deny)
+        {
+            return Err(PolicyError::Denied(cmd.to_cmd_string()));
+        }
+
+        let now = Instant::now();
+        let mut histories = Vec::with_capacity(self.rate_limits.len());
+        for rate_limit in &self.rate_limits {
+            let count = cmds
+                .iter()
+                .filter(|cmd| rate_limit.commands.is_match(cmd.name()))
+                .count();
+            let history = rate_limit.lock_history(now);
+            if history.len() + count > rate_limit.max {
+                return Err(PolicyError::RateLimited {
+                    pattern: rate_limit.commands.to_string(),
+                    max: rate_limit.max,
+                    window: rate_limit.window,
+                });
+            }
+            histories.push((history, count));
+        }
+
+        if record {
+            for (mut history, count) in histories {
+                history.extend(std::iter::repeat_n(now, count));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nagios::cmd::{
+        DisableHostCheck, DisableHostNotifications, DisableHostgroupHostChecks, EnableHostCheck,
+        RawCmd,
+    };
+
+    fn disable_host_check(host_name: &str) -> Box<dyn NagiosCmd> {
+        Box::new(DisableHostCheck {
+            host_name: host_name.to_string(),
+        })
+    }
+
+    fn team_a_policy() -> CommandPolicy {
+        CommandPolicy::deny_by_default()
+            .deny(Rule::new().commands(Regex::new("^SHUTDOWN_PROGRAM$").unwrap()))
+            .allow(
+                Rule::new()
+                    .commands(Regex::new("^(ENABLE|DISABLE)_HOST_").unwrap())
+                    .hosts(Regex::new("^team-a-").unwrap()),
+            )
+            .allow(Rule::new().hostgroups(Regex::new("^team-a$").unwrap()))
+    }
+
+    #[test]
+    fn test_decide() {
+        struct TestCase(Box<dyn NagiosCmd>, Decision);
+        let test_cases = vec![
+            TestCase(disable_host_check("team-a-web01"), Decision::Allow),
+            TestCase(disable_host_check("team-b-web01"), Decision::Deny),
+            TestCase(
+                Box::new(EnableHostCheck {
+                    host_name: "team-a-db01".to_string(),
+                }),
+                Decision::Allow,
+            ),
+            TestCase(
+                Box::new(DisableHostgroupHostChecks {
+                    hostgroup_name: "team-a".to_string(),
+                }),
+                Decision::Allow,
+            ),
+            TestCase(
+                Box::new(DisableHostgroupHostChecks {
+                    hostgroup_name: "team-b".to_string(),
+                }),
+                Decision::Deny,
+            ),
+        ];
+
+        let policy = team_a_policy();
+        for test_case in test_cases {
+            assert_eq!(policy.decide(test_case.0.as_ref()), test_case.1);
+        }
+    }
+
+    #[test]
+    fn test_decide_raw_cmd() {
+        let policy = CommandPolicy::allow_by_default()
+            .deny(Rule::new().hosts(Regex::new("^prod-").unwrap()))
+            .deny(Rule::new().hostgroups(Regex::new("^prod$").unwrap()));
+        struct TestCase<'a>(&'a str, Decision);
+        let test_cases = vec![
+            TestCase("DEL_HOST_DOWNTIME;12", Decision::Allow),
+            TestCase("DEL_DOWNTIME_BY_HOST_NAME;prod-web01", Decision::Deny),
+            TestCase("DEL_DOWNTIME_BY_HOST_NAME;dev-web01", Decision::Allow),
+            TestCase("DEL_DOWNTIME_BY_HOSTGROUP_NAME;prod", Decision::Deny),
+            TestCase("DEL_ALL_HOST_COMMENTS;prod-web01", Decision::Deny),
+            TestCase("DEL_ALL_SVC_COMMENTS;prod-web01;HTTP", Decision::Deny),
+            TestCase(
+                "CHANGE_GLOBAL_HOST_EVENT_HANDLER;prod-handler",
+                Decision::Allow,
+            ),
+            TestCase("START_EXECUTING_HOST_CHECKS", Decision::Allow),
+        ];
+        for test_case in test_cases {
+            let cmd = test_case.0.parse::<RawCmd>().unwrap();
+            assert_eq!(policy.decide(&cmd), test_case.1, "{}", test_case.0);
+        }
+    }
+
+    #[test]
+    fn test_check_denied() {
+        let policy = team_a_policy();
+        let cmds = vec![
+            disable_host_check("team-a-web01"),
+            disable_host_check("team-b-web01"),
+        ];
+        assert_eq!(
+            policy.check(&cmds),
+            Err(PolicyError::Denied(
+                "DISABLE_HOST_CHECK;team-b-web01".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_admit_rate_limited() {
+        let policy = CommandPolicy::allow_by_default().rate_limit(
+            Regex::new("^DISABLE_").unwrap(),
+            3,
+            Duration::from_secs(60),
+        );
+        let enable: Box<dyn NagiosCmd> = Box::new(EnableHostCheck {
+            host_name: "web01".to_string(),
+        });
+        let disable_notifications: Box<dyn NagiosCmd> = Box::new(DisableHostNotifications {
+            host_name: "web01".to_string(),
+        });
+
+        // check does not count against the limit
+        assert_eq!(policy.check(&[disable_host_check("web01")]), Ok(()));
+        assert_eq!(
+            policy.admit(&[disable_host_check("web01"), disable_notifications, enable]),
+            Ok(())
+        );
+        // a batch that would exceed the limit is rejected as a whole
+        assert!(matches!(
+            policy.admit(&[disable_host_check("web02"), disable_host_check("web03")]),
+            Err(PolicyError::RateLimited { max: 3, .. })
+        ));
+        assert_eq!(policy.admit(&[disable_host_check("web02")]), Ok(()));
+        assert!(policy.admit(&[disable_host_check("web03")]).is_err());
+    }
+
+    #[test]
+    fn test_rate_limit_window() {
+        let policy = CommandPolicy::allow_by_default().rate_limit(
+            Regex::new("^DISABLE_").unwrap(),
+            1,
+            Duration::from_millis(20),
+        );
+        assert_eq!(policy.admit(&[disable_host_check("web01")]), Ok(()));
+        assert!(policy.admit(&[disable_host_check("web01")]).is_err());
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(policy.admit(&[disable_host_check("web01")]), Ok(()));
+    }
+}
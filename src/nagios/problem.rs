@@ -0,0 +1,173 @@
+use serde::Serialize;
+
+use super::object::{Host, HostState, Service, ServiceState, StateType};
+
+/// Why a host or service in a non-OK state does or does not need attention.
+/// When several apply, the first in declaration order wins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub enum ProblemClass {
+    /// In scheduled downtime.
+    InDowntime,
+    Acknowledged,
+    /// A service whose host is down or unreachable.
+    HostDown,
+    /// Active checks are disabled, so the state may be stale.
+    ChecksDisabled,
+    /// Not yet confirmed by `max_attempts` checks.
+    Soft,
+    Unhandled,
+}
+
+impl ProblemClass {
+    /// Whether someone is already on the problem: it is in downtime,
+    /// acknowledged or caused by its host.
+    pub fn is_handled(&self) -> bool {
+        matches!(
+            self,
+            ProblemClass::InDowntime | ProblemClass::Acknowledged | ProblemClass::HostDown
+        )
+    }
+}
+
+fn classify(
+    scheduled_downtime_depth: u32,
+    acknowledged: bool,
+    host_down: bool,
+    active_checks_enabled: bool,
+    state_type: &StateType,
+) -> ProblemClass {
+    if scheduled_downtime_depth > 0 {
+        ProblemClass::InDowntime
+    } else if acknowledged {
+        ProblemClass::Acknowledged
+    } else if host_down {
+        ProblemClass::HostDown
+    } else if !active_checks_enabled {
+        ProblemClass::ChecksDisabled
+    } else if *state_type == StateType::Soft {
+        ProblemClass::Soft
+    } else {
+        ProblemClass::Unhandled
+    }
+}
+
+impl Host {
+    /// `None` if the host is up or has not been checked yet.
+    pub fn problem_class(&self) -> Option<ProblemClass> {
+        if !self.has_been_checked || self.current_state == HostState::Up {
+            return None;
+        }
+        Some(classify(
+            self.scheduled_downtime_depth,
+            self.problem_has_been_acknowledged,
+            false,
+            self.active_checks_enabled,
+            &self.state_type,
+        ))
+    }
+}
+
+impl Service {
+    /// `None` if the service is OK or has not been checked yet. `host` is the
+    /// service's host, if known.
+    pub fn problem_class(&self, host: Option<&Host>) -> Option<ProblemClass> {
+        if !self.has_been_checked || self.current_state == ServiceState::Ok {
+            return None;
+        }
+        Some(classify(
+            self.scheduled_downtime_depth,
+            self.problem_has_been_acknowledged,
+            host.is_some_and(|host| host.current_state != HostState::Up),
+            self.active_checks_enabled,
+            &self.state_type,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nagios::NagiosStatus;
+
+    #[test]
+    fn test_service_problem_class() {
+        let status = NagiosStatus::parse_file("testdata/status.dat").unwrap();
+        let host = status.get_host("localhost").unwrap();
+        let mut down_host = host.clone();
+        down_host.current_state = HostState::Down;
+
+        let mut base = status.get_host_services("localhost").unwrap()[0].clone();
+        base.has_been_checked = true;
+        base.current_state = ServiceState::Critical;
+        base.state_type = StateType::Hard;
+        base.active_checks_enabled = true;
+
+        struct TestCase<'a>(
+            Box<dyn Fn(&mut Service)>,
+            Option<&'a Host>,
+            Option<ProblemClass>,
+        );
+        let test_cases = vec![
+            TestCase(Box::new(|_| {}), Some(&host), Some(ProblemClass::Unhandled)),
+            TestCase(
+                Box::new(|s| s.current_state = ServiceState::Ok),
+                Some(&host),
+                None,
+            ),
+            TestCase(Box::new(|s| s.has_been_checked = false), Some(&host), None),
+            TestCase(
+                Box::new(|s| s.state_type = StateType::Soft),
+                Some(&host),
+                Some(ProblemClass::Soft),
+            ),
+            TestCase(
+                Box::new(|s| {
+                    s.state_type = StateType::Soft;
+                    s.active_checks_enabled = false;
+                }),
+                Some(&host),
+                Some(ProblemClass::ChecksDisabled),
+            ),
+            TestCase(
+                Box::new(|_| {}),
+                Some(&down_host),
+                Some(ProblemClass::HostDown),
+            ),
+            TestCase(Box::new(|_| {}), None, Some(ProblemClass::Unhandled)),
+            TestCase(
+                Box::new(|s| s.problem_has_been_acknowledged = true),
+                Some(&down_host),
+                Some(ProblemClass::Acknowledged),
+            ),
+            TestCase(
+                Box::new(|s| {
+                    s.problem_has_been_acknowledged = true;
+                    s.scheduled_downtime_depth = 1;
+                }),
+                Some(&host),
+                Some(ProblemClass::InDowntime),
+            ),
+        ];
+        for test_case in test_cases {
+            let mut service = base.clone();
+            (test_case.0)(&mut service);
+            assert_eq!(service.problem_class(test_case.1), test_case.2);
+        }
+    }
+
+    #[test]
+    fn test_host_problem_class() {
+        let status = NagiosStatus::parse_file("testdata/status.dat").unwrap();
+        let mut host = status.get_host("localhost").unwrap();
+        assert_eq!(host.problem_class(), None);
+
+        host.current_state = HostState::Unreachable;
+        host.state_type = StateType::Hard;
+        assert_eq!(host.problem_class(), Some(ProblemClass::Unhandled));
+        assert!(!host.problem_class().unwrap().is_handled());
+
+        host.problem_has_been_acknowledged = true;
+        assert_eq!(host.problem_class(), Some(ProblemClass::Acknowledged));
+        assert!(host.problem_class().unwrap().is_handled());
+    }
+}
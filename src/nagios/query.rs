@@ -0,0 +1,1009 @@
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops;
+use std::str::FromStr;
+use thiserror::Error;
+
+use super::object::{
+    AcknowledgementType, CheckOptions, CheckType, Host, HostState, ModifiedAttributes, Service,
+    ServiceState, StateType,
+};
+use super::NagiosStatus;
+
+////////////////////////////////////
+// error
+
+#[derive(Error, Debug, PartialEq)]
+pub enum QueryError {
+    #[error("unexpected token at {0}: {1}")]
+    UnexpectedToken(usize, String),
+    #[error("unexpected end of filter")]
+    UnexpectedEnd,
+    #[error("unterminated string at {0}")]
+    UnterminatedString(usize),
+    #[error("invalid regex: {0}")]
+    InvalidRegex(String),
+}
+
+////////////////////////////////////
+// value
+
+/// A field value. Times are unix timestamps with `0` for never, as in
+/// status.dat.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    /// The variant names of an enum and the index of the value, which is its
+    /// status.dat code. Ordered by code; compares with a variant name
+    /// (`"Critical"`) or a code (`2`).
+    Enum(&'static [&'static str], usize),
+}
+
+impl Value {
+    fn partial_cmp(&self, other: &Value) -> Option<Ordering> {
+        match (self, other) {
+            (Value::Bool(a), Value::Bool(b)) => a.partial_cmp(b),
+            (Value::Int(a), Value::Int(b)) => a.partial_cmp(b),
+            (Value::Str(a), Value::Str(b)) => a.partial_cmp(b),
+            (Value::Enum(names, code), Value::Str(name)) => {
+                let other = names.iter().position(|n| n == name)?;
+                code.partial_cmp(&other)
+            }
+            (Value::Str(_), Value::Enum(..)) => other.partial_cmp(self).map(Ordering::reverse),
+            (a, b) => a.as_f64()?.partial_cmp(&b.as_f64()?),
+        }
+    }
+
+    fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Int(i) => Some(*i as f64),
+            Value::Float(f) => Some(*f),
+            Value::Enum(_, code) => Some(*code as f64),
+            _ => None,
+        }
+    }
+
+    /// The text of strings and the variant name of enums.
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Str(s) => Some(s),
+            Value::Enum(names, code) => Some(names[*code]),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Int(i) => write!(f, "{}", i),
+            Value::Float(x) => write!(f, "{:?}", x),
+            Value::Str(s) => write!(f, "{:?}", s),
+            Value::Enum(names, code) => write!(f, "{:?}", names[*code]),
+        }
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+impl From<u32> for Value {
+    fn from(u: u32) -> Self {
+        Value::Int(u.into())
+    }
+}
+
+impl From<i32> for Value {
+    fn from(i: i32) -> Self {
+        Value::Int(i.into())
+    }
+}
+
+impl From<i64> for Value {
+    fn from(i: i64) -> Self {
+        Value::Int(i)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(x: f64) -> Self {
+        Value::Float(x)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::Str(s.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::Str(s)
+    }
+}
+
+impl From<Option<DateTime<Utc>>> for Value {
+    fn from(time: Option<DateTime<Utc>>) -> Self {
+        Value::Int(time.map_or(0, |time| time.timestamp()))
+    }
+}
+
+impl From<ModifiedAttributes> for Value {
+    fn from(attributes: ModifiedAttributes) -> Self {
+        attributes.bits().into()
+    }
+}
+
+impl From<CheckOptions> for Value {
+    fn from(options: CheckOptions) -> Self {
+        options.bits().into()
+    }
+}
+
+/// The enums are declared in the order of their status.dat codes, so the
+/// discriminant is the code.
+macro_rules! enum_value {
+    ($($ty:ty [$($variant:ident),*]),* $(,)?) => {
+        $(
+            impl From<$ty> for Value {
+                fn from(e: $ty) -> Self {
+                    Value::Enum(&[$(stringify!($variant)),*], e.code() as usize)
+                }
+            }
+        )*
+    };
+}
+
+enum_value!(
+    HostState [Up, Down, Unreachable],
+    ServiceState [Ok, Warning, Critical, Unknown],
+    StateType [Soft, Hard],
+    CheckType [Active, Passive, Parent, File, Other],
+    AcknowledgementType [None, Normal, Sticky],
+);
+
+////////////////////////////////////
+// record
+
+/// An object whose fields can be looked up by name.
+pub trait Record {
+    fn field(&self, name: &str) -> Option<Value>;
+}
+
+macro_rules! record {
+    ($ty:ty { $($field:ident),* $(,)? }) => {
+        impl Record for $ty {
+            fn field(&self, name: &str) -> Option<Value> {
+                match name {
+                    $(stringify!($field) => Some(self.$field.clone().into()),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+record!(Host {
+    host_name,
+    modified_attributes,
+    check_command,
+    check_period,
+    notification_period,
+    importance,
+    check_interval,
+    retry_interval,
+    event_handler,
+    has_been_checked,
+    should_be_scheduled,
+    check_execution_time,
+    check_latency,
+    check_type,
+    current_state,
+    last_hard_state,
+    plugin_output,
+    long_plugin_output,
+    performance_data,
+    last_check,
+    next_check,
+    check_options,
+    current_attempt,
+    max_attempts,
+    state_type,
+    last_state_change,
+    last_hard_state_change,
+    last_time_up,
+    last_time_down,
+    last_time_unreachable,
+    last_notification,
+    next_notification,
+    no_more_notifications,
+    current_notification_number,
+    notifications_enabled,
+    problem_has_been_acknowledged,
+    acknowledgement_type,
+    active_checks_enabled,
+    passive_checks_enabled,
+    event_handler_enabled,
+    flap_detection_enabled,
+    process_performance_data,
+    obsess,
+    last_update,
+    is_flapping,
+    percent_state_change,
+    scheduled_downtime_depth,
+});
+
+record!(Service {
+    host_name,
+    service_description,
+    modified_attributes,
+    check_command,
+    check_period,
+    notification_period,
+    importance,
+    check_interval,
+    retry_interval,
+    event_handler,
+    has_been_checked,
+    should_be_scheduled,
+    check_execution_time,
+    check_latency,
+    check_type,
+    current_state,
+    last_hard_state,
+    current_attempt,
+    max_attempts,
+    state_type,
+    last_state_change,
+    last_hard_state_change,
+    last_time_ok,
+    last_time_warning,
+    last_time_unknown,
+    last_time_critical,
+    plugin_output,
+    long_plugin_output,
+    performance_data,
+    last_check,
+    next_check,
+    check_options,
+    current_notification_number,
+    last_notification,
+    next_notification,
+    no_more_notifications,
+    notifications_enabled,
+    active_checks_enabled,
+    passive_checks_enabled,
+    event_handler_enabled,
+    problem_has_been_acknowledged,
+    acknowledgement_type,
+    flap_detection_enabled,
+    process_performance_data,
+    obsess,
+    last_update,
+    is_flapping,
+    percent_state_change,
+    scheduled_downtime_depth,
+});
+
+////////////////////////////////////
+// filter
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Op {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Op::Eq => "==",
+            Op::Ne => "!=",
+            Op::Lt => "<",
+            Op::Le => "<=",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+        }
+    }
+}
+
+/// A boolean expression over the fields of a `Record`.
+///
+/// Comparisons against a field the record does not have, or between values
+/// of different kinds, are false (and `!=` is true).
+#[derive(Debug, Clone)]
+pub enum Filter {
+    Cmp(String, Op, Value),
+    Match(String, Regex),
+    And(Vec<Filter>),
+    Or(Vec<Filter>),
+    Not(Box<Filter>),
+}
+
+impl Filter {
+    pub fn eq<V: Into<Value>>(field: &str, value: V) -> Filter {
+        Filter::Cmp(field.to_string(), Op::Eq, value.into())
+    }
+
+    pub fn ne<V: Into<Value>>(field: &str, value: V) -> Filter {
+        Filter::Cmp(field.to_string(), Op::Ne, value.into())
+    }
+
+    pub fn lt<V: Into<Value>>(field: &str, value: V) -> Filter {
+        Filter::Cmp(field.to_string(), Op::Lt, value.into())
+    }
+
+    pub fn le<V: Into<Value>>(field: &str, value: V) -> Filter {
+        Filter::Cmp(field.to_string(), Op::Le, value.into())
+    }
+
+    pub fn gt<V: Into<Value>>(field: &str, value: V) -> Filter {
+        Filter::Cmp(field.to_string(), Op::Gt, value.into())
+    }
+
+    pub fn ge<V: Into<Value>>(field: &str, value: V) -> Filter {
+        Filter::Cmp(field.to_string(), Op::Ge, value.into())
+    }
+
+    pub fn matches(field: &str, re: Regex) -> Filter {
+        Filter::Match(field.to_string(), re)
+    }
+
+    /// Shorthand for `field == true`.
+    pub fn is(field: &str) -> Filter {
+        Filter::eq(field, true)
+    }
+
+    pub fn and(self, other: Filter) -> Filter {
+        match self {
+            Filter::And(mut filters) => {
+                filters.push(other);
+                Filter::And(filters)
+            }
+            filter => Filter::And(vec![filter, other]),
+        }
+    }
+
+    pub fn or(self, other: Filter) -> Filter {
+        match self {
+            Filter::Or(mut filters) => {
+                filters.push(other);
+                Filter::Or(filters)
+            }
+            filter => Filter::Or(vec![filter, other]),
+        }
+    }
+
+    pub fn is_match<R: Record + ?Sized>(&self, record: &R) -> bool {
+        match self {
+            Filter::Cmp(field, op, value) => {
+                let ordering = record
+                    .field(field)
+                    .and_then(|field| field.partial_cmp(value));
+                match op {
+                    Op::Eq => ordering == Some(Ordering::Equal),
+                    Op::Ne => ordering != Some(Ordering::Equal),
+                    Op::Lt => ordering == Some(Ordering::Less),
+                    Op::Le => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+                    Op::Gt => ordering == Some(Ordering::Greater),
+                    Op::Ge => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+                }
+            }
+            Filter::Match(field, re) => record
+                .field(field)
+                .is_some_and(|value| value.as_str().is_some_and(|s| re.is_match(s))),
+            Filter::And(filters) => filters.iter().all(|filter| filter.is_match(record)),
+            Filter::Or(filters) => filters.iter().any(|filter| filter.is_match(record)),
+            Filter::Not(filter) => !filter.is_match(record),
+        }
+    }
+}
+
+impl ops::Not for Filter {
+    type Output = Filter;
+
+    fn not(self) -> Filter {
+        Filter::Not(Box::new(self))
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let join = |f: &mut fmt::Formatter<'_>, filters: &[Filter], sep: &str| {
+            write!(f, "(")?;
+            for (i, filter) in filters.iter().enumerate() {
+                if i > 0 {
+                    write!(f, " {} ", sep)?;
+                }
+                write!(f, "{}", filter)?;
+            }
+            write!(f, ")")
+        };
+        match self {
+            Filter::Cmp(field, op, value) => write!(f, "{} {} {}", field, op.as_str(), value),
+            Filter::Match(field, re) => write!(f, "{} =~ {:?}", field, re.as_str()),
+            Filter::And(filters) => join(f, filters, "&&"),
+            Filter::Or(filters) => join(f, filters, "||"),
+            Filter::Not(filter) => write!(f, "!{}", filter),
+        }
+    }
+}
+
+////////////////////////////////////
+// filter language
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(f64, bool),
+    Str(String),
+    Op(Op),
+    Match,
+    And,
+    Or,
+    Not,
+    LParen,
+    RParen,
+}
+
+fn tokenize(s: &str) -> Result<Vec<(usize, Token)>, QueryError> {
+    let chars: Vec<(usize, char)> = s.char_indices().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let (pos, c) = chars[i];
+        let next = chars.get(i + 1).map(|(_, c)| *c);
+        let (token, len) = match (c, next) {
+            (c, _) if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            ('&', Some('&')) => (Token::And, 2),
+            ('|', Some('|')) => (Token::Or, 2),
+            ('=', Some('=')) => (Token::Op(Op::Eq), 2),
+            ('=', Some('~')) => (Token::Match, 2),
+            ('!', Some('=')) => (Token::Op(Op::Ne), 2),
+            ('<', Some('=')) => (Token::Op(Op::Le), 2),
+            ('>', Some('=')) => (Token::Op(Op::Ge), 2),
+            ('<', _) => (Token::Op(Op::Lt), 1),
+            ('>', _) => (Token::Op(Op::Gt), 1),
+            ('!', _) => (Token::Not, 1),
+            ('(', _) => (Token::LParen, 1),
+            (')', _) => (Token::RParen, 1),
+            ('"', _) | ('\'', _) => {
+                let mut value = String::new();
+                let mut j = i + 1;
+                loop {
+                    match chars.get(j) {
+                        None => return Err(QueryError::UnterminatedString(pos)),
+                        Some((_, '\\')) => {
+                            if let Some((_, escaped)) = chars.get(j + 1) {
+                                value.push(*escaped);
+                            }
+                            j += 2;
+                        }
+                        Some((_, quote)) if *quote == c => break,
+                        Some((_, other)) => {
+                            value.push(*other);
+                            j += 1;
+                        }
+                    }
+                }
+                (Token::Str(value), j + 1 - i)
+            }
+            (c, _) if c.is_ascii_digit() || c == '-' || c == '.' => {
+                let len = chars[i..]
+                    .iter()
+                    .skip(1)
+                    .take_while(|(_, c)| c.is_ascii_digit() || *c == '.')
+                    .count()
+                    + 1;
+                let end = chars.get(i + len).map_or(s.len(), |(end, _)| *end);
+                let number = &s[pos..end];
+                let value = number
+                    .parse::<f64>()
+                    .map_err(|_| QueryError::UnexpectedToken(pos, number.to_string()))?;
+                (Token::Number(value, number.contains('.')), len)
+            }
+            (c, _) if c.is_alphanumeric() || c == '_' => {
+                let len = chars[i..]
+                    .iter()
+                    .take_while(|(_, c)| c.is_alphanumeric() || *c == '_')
+                    .count();
+                let end = chars.get(i + len).map_or(s.len(), |(end, _)| *end);
+                (Token::Ident(s[pos..end].to_string()), len)
+            }
+            (c, _) => return Err(QueryError::UnexpectedToken(pos, c.to_string())),
+        };
+        tokens.push((pos, token));
+        i += len;
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent parser:
+///
+/// ```text
+/// or      = and ("||" and)*
+/// and     = unary ("&&" unary)*
+/// unary   = "!" unary | "(" or ")" | field [op literal | "=~" string]
+/// literal = number | string | true | false | word
+/// ```
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, token)| token)
+    }
+
+    fn next(&mut self) -> Result<(usize, Token), QueryError> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or(QueryError::UnexpectedEnd)?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn or(&mut self) -> Result<Filter, QueryError> {
+        let mut filter = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            filter = filter.or(self.and()?);
+        }
+        Ok(filter)
+    }
+
+    fn and(&mut self) -> Result<Filter, QueryError> {
+        let mut filter = self.unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            filter = filter.and(self.unary()?);
+        }
+        Ok(filter)
+    }
+
+    fn unary(&mut self) -> Result<Filter, QueryError> {
+        match self.next()? {
+            (_, Token::Not) => Ok(!self.unary()?),
+            (_, Token::LParen) => {
+                let filter = self.or()?;
+                match self.next()? {
+                    (_, Token::RParen) => Ok(filter),
+                    (pos, token) => Err(unexpected(pos, token)),
+                }
+            }
+            (_, Token::Ident(field)) => match self.peek() {
+                Some(Token::Op(op)) => {
+                    let op = *op;
+                    self.pos += 1;
+                    Ok(Filter::Cmp(field, op, self.literal()?))
+                }
+                Some(Token::Match) => {
+                    self.pos += 1;
+                    match self.next()? {
+                        (_, Token::Str(re)) => Regex::new(&re)
+                            .map(|re| Filter::Match(field, re))
+                            .map_err(|error| QueryError::InvalidRegex(error.to_string())),
+                        (pos, token) => Err(unexpected(pos, token)),
+                    }
+                }
+                _ => Ok(Filter::is(&field)),
+            },
+            (pos, token) => Err(unexpected(pos, token)),
+        }
+    }
+
+    fn literal(&mut self) -> Result<Value, QueryError> {
+        match self.next()? {
+            (_, Token::Number(n, true)) => Ok(Value::Float(n)),
+            (_, Token::Number(n, false)) => Ok(Value::Int(n as i64)),
+            (_, Token::Str(s)) => Ok(Value::Str(s)),
+            (_, Token::Ident(word)) => Ok(match word.as_str() {
+                "true" => Value::Bool(true),
+                "false" => Value::Bool(false),
+                _ => Value::Str(word),
+            }),
+            (pos, token) => Err(unexpected(pos, token)),
+        }
+    }
+}
+
+fn unexpected(pos: usize, token: Token) -> QueryError {
+    QueryError::UnexpectedToken(pos, format!("{:?}", token))
+}
+
+impl FromStr for Filter {
+    type Err = QueryError;
+
+    /// Parses e.g. `current_state != Ok && state_type == Hard &&
+    /// !problem_has_been_acknowledged && host_name =~ "^web"`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            pos: 0,
+        };
+        let filter = parser.or()?;
+        match parser.tokens.get(parser.pos) {
+            Some((pos, token)) => Err(unexpected(*pos, token.clone())),
+            None => Ok(filter),
+        }
+    }
+}
+
+////////////////////////////////////
+// query
+
+/// A filter plus sort keys and a limit.
+#[derive(Debug, Clone, Default)]
+pub struct Query {
+    filter: Option<Filter>,
+    sort: Vec<(String, bool)>,
+    limit: Option<usize>,
+}
+
+impl Query {
+    pub fn new() -> Query {
+        Query::default()
+    }
+
+    /// Adds `filter`, and-ed with any filter already set.
+    pub fn filter(mut self, filter: Filter) -> Query {
+        self.filter = Some(match self.filter {
+            Some(current) => current.and(filter),
+            None => filter,
+        });
+        self
+    }
+
+    /// Sorts ascending by `field`. Later sort keys break ties of earlier ones.
+    pub fn sort_by(mut self, field: &str) -> Query {
+        self.sort.push((field.to_string(), false));
+        self
+    }
+
+    pub fn sort_by_desc(mut self, field: &str) -> Query {
+        self.sort.push((field.to_string(), true));
+        self
+    }
+
+    pub fn limit(mut self, limit: usize) -> Query {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn is_match<R: Record + ?Sized>(&self, record: &R) -> bool {
+        self.filter
+            .as_ref()
+            .is_none_or(|filter| filter.is_match(record))
+    }
+
+    /// Filters, sorts and truncates `records`, keeping their order on ties.
+    pub fn apply<'a, R, I>(&self, records: I) -> Vec<&'a R>
+    where
+        R: Record + 'a,
+        I: IntoIterator<Item = &'a R>,
+    {
+        let mut records: Vec<&R> = records
+            .into_iter()
+            .filter(|record| self.is_match(*record))
+            .collect();
+        if !self.sort.is_empty() {
+            records.sort_by(|a, b| {
+                self.sort
+                    .iter()
+                    .map(|(field, descending)| {
+                        let ordering = match (a.field(field), b.field(field)) {
+                            (Some(a), Some(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+                            _ => Ordering::Equal,
+                        };
+                        if *descending {
+                            ordering.reverse()
+                        } else {
+                            ordering
+                        }
+                    })
+                    .find(|ordering| *ordering != Ordering::Equal)
+                    .unwrap_or(Ordering::Equal)
+            });
+        }
+        if let Some(limit) = self.limit {
+            records.truncate(limit);
+        }
+        records
+    }
+}
+
+impl Query {
+    /// The `X` of a top-level `current_state == X`, which lets the state
+    /// index narrow down the candidates.
+    fn current_state(&self) -> Option<&str> {
+        let is_hint = |filter: &&Filter| matches!(filter, Filter::Cmp(field, Op::Eq, Value::Str(_) | Value::Enum(..)) if field == "current_state");
+        let hint = match self.filter.as_ref()? {
+            Filter::And(filters) => filters.iter().find(is_hint)?,
+            filter => Some(filter).filter(is_hint)?,
+        };
+        match hint {
+            Filter::Cmp(_, _, value) => value.as_str(),
+            _ => None,
+        }
+    }
+}
+
+impl NagiosStatus {
+    /// Hosts matching `query`, ordered by host name unless it sorts them.
+    pub fn query_hosts(&self, query: &Query) -> Vec<&Host> {
+        let state = match query.current_state() {
+            Some("Up") => Some(HostState::Up),
+            Some("Down") => Some(HostState::Down),
+            Some("Unreachable") => Some(HostState::Unreachable),
+            _ => None,
+        };
+        let hosts = match state {
+            Some(state) => self.hosts_in_state(&state),
+            None => {
+                let mut hosts: Vec<&Host> = self.hosts.values().collect();
+                hosts.sort_by(|a, b| a.host_name.cmp(&b.host_name));
+                hosts
+            }
+        };
+        query.apply(hosts)
+    }
+
+    /// Services matching `query`, ordered by host name and description unless
+    /// it sorts them.
+    pub fn query_services(&self, query: &Query) -> Vec<&Service> {
+        let state = match query.current_state() {
+            Some("Ok") => Some(ServiceState::Ok),
+            Some("Warning") => Some(ServiceState::Warning),
+            Some("Critical") => Some(ServiceState::Critical),
+            Some("Unknown") => Some(ServiceState::Unknown),
+            _ => None,
+        };
+        let services = match state {
+            Some(state) => self.services_in_state(&state),
+            None => {
+                let mut services: Vec<&Service> = self.services.values().flatten().collect();
+                services.sort_by(|a, b| {
+                    (&a.host_name, &a.service_description)
+                        .cmp(&(&b.host_name, &b.service_description))
+                });
+                services
+            }
+        };
+        query.apply(services)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status() -> NagiosStatus {
+        let mut status = NagiosStatus::parse_file("testdata/status.dat").unwrap();
+        for service in status.services.get_mut("localhost").unwrap() {
+            match service.service_description.as_str() {
+                "HTTP" => {
+                    service.current_state = ServiceState::Critical;
+                    service.state_type = StateType::Hard;
+                    service.last_state_change = DateTime::from_timestamp(200, 0);
+                }
+                "PING" => {
+                    service.current_state = ServiceState::Warning;
+                    service.state_type = StateType::Hard;
+                    service.last_state_change = DateTime::from_timestamp(100, 0);
+                }
+                "Total Processes" => {
+                    service.current_state = ServiceState::Critical;
+                    service.state_type = StateType::Hard;
+                    service.problem_has_been_acknowledged = true;
+                }
+                "Swap Usage" => {
+                    service.current_state = ServiceState::Critical;
+                    service.state_type = StateType::Soft;
+                }
+                _ => {}
+            }
+        }
+        status.reindex();
+        status
+    }
+
+    fn descriptions(services: Vec<&Service>) -> Vec<&str> {
+        services
+            .iter()
+            .map(|service| service.service_description.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_filter() {
+        struct TestCase<'a>(&'a str, Result<&'a str, QueryError>);
+        let test_cases = vec![
+            TestCase("current_state != Ok", Ok("current_state != \"Ok\"")),
+            TestCase("importance >= 2", Ok("importance >= 2")),
+            TestCase(
+                "percent_state_change < 1.5",
+                Ok("percent_state_change < 1.5"),
+            ),
+            TestCase("is_flapping", Ok("is_flapping == true")),
+            TestCase("!is_flapping", Ok("!is_flapping == true")),
+            TestCase(
+                "host_name =~ '^web' || host_name == \"db 1\"",
+                Ok("(host_name =~ \"^web\" || host_name == \"db 1\")"),
+            ),
+            TestCase(
+                "a && b || c && !(d || e)",
+                Ok("((a == true && b == true) || (c == true && !(d == true || e == true)))"),
+            ),
+            TestCase("a ==", Err(QueryError::UnexpectedEnd)),
+            TestCase(
+                "a == 1 b",
+                Err(QueryError::UnexpectedToken(7, "Ident(\"b\")".to_string())),
+            ),
+            TestCase("a == 'x", Err(QueryError::UnterminatedString(5))),
+            TestCase(
+                "a & b",
+                Err(QueryError::UnexpectedToken(2, "&".to_string())),
+            ),
+            TestCase("(a", Err(QueryError::UnexpectedEnd)),
+        ];
+        for test_case in test_cases {
+            assert_eq!(
+                test_case
+                    .0
+                    .parse::<Filter>()
+                    .map(|filter| filter.to_string()),
+                test_case.1.map(|s| s.to_string()),
+                "{}",
+                test_case.0
+            );
+        }
+        assert!(matches!(
+            "a =~ '('".parse::<Filter>(),
+            Err(QueryError::InvalidRegex(_))
+        ));
+    }
+
+    #[test]
+    fn test_query_services() {
+        let status = status();
+        let unhandled = "current_state != Ok && state_type == Hard \
+                         && !problem_has_been_acknowledged && scheduled_downtime_depth == 0";
+
+        let query = Query::new()
+            .filter(unhandled.parse().unwrap())
+            .sort_by("last_state_change");
+        assert_eq!(
+            descriptions(status.query_services(&query)),
+            vec!["PING", "HTTP"]
+        );
+
+        let query = Query::new()
+            .filter(Filter::ne("current_state", ServiceState::Ok))
+            .filter(Filter::eq("state_type", StateType::Hard))
+            .filter(!Filter::is("problem_has_been_acknowledged"))
+            .filter(Filter::eq("scheduled_downtime_depth", 0))
+            .sort_by_desc("last_state_change")
+            .limit(1);
+        assert_eq!(descriptions(status.query_services(&query)), vec!["HTTP"]);
+    }
+
+    #[test]
+    fn test_filter_is_match() {
+        let status = status();
+        let service = status
+            .query_services(&Query::new().filter(Filter::eq("service_description", "HTTP")))[0];
+
+        struct TestCase<'a>(&'a str, bool);
+        let test_cases = vec![
+            TestCase("current_state == Critical", true),
+            TestCase("current_state == 'Critical' && state_type == Soft", false),
+            TestCase("service_description =~ '^HT'", true),
+            TestCase("last_state_change > 199 && last_state_change <= 200", true),
+            TestCase("check_interval == 5", true),
+            TestCase("last_notification == 0", true),
+            // enums compare with their status.dat code
+            TestCase("current_state == 2", true),
+            TestCase("current_state > Warning && current_state < 3", true),
+            TestCase("current_state >= Unknown", false),
+            TestCase("current_state =~ '^Crit'", true),
+            // kind mismatches and unknown fields never compare equal
+            TestCase("current_state == true", false),
+            TestCase("current_state != true", true),
+            TestCase("current_state == Up", false),
+            TestCase("no_such_field == 1", false),
+            TestCase("no_such_field", false),
+        ];
+        for test_case in test_cases {
+            let filter: Filter = test_case.0.parse().unwrap();
+            assert_eq!(filter.is_match(service), test_case.1, "{}", test_case.0);
+        }
+    }
+
+    #[test]
+    fn test_query_by_state_index() {
+        let status = status();
+        let query = Query::new().filter("current_state == Critical".parse().unwrap());
+        assert_eq!(
+            descriptions(status.query_services(&query)),
+            vec!["HTTP", "Swap Usage", "Total Processes"]
+        );
+        let query = Query::new()
+            .filter(Filter::eq("state_type", StateType::Hard))
+            .filter(Filter::eq("current_state", ServiceState::Critical));
+        assert_eq!(
+            descriptions(status.query_services(&query)),
+            vec!["HTTP", "Total Processes"]
+        );
+    }
+
+    #[test]
+    fn test_query_by_state_order() {
+        let status = status();
+        struct TestCase<'a>(Query, Vec<&'a str>);
+        let test_cases = vec![
+            TestCase(
+                Query::new().filter("current_state >= Warning".parse().unwrap()),
+                vec!["HTTP", "PING", "Swap Usage", "Total Processes"],
+            ),
+            TestCase(
+                Query::new().filter(Filter::gt("current_state", ServiceState::Warning)),
+                vec!["HTTP", "Swap Usage", "Total Processes"],
+            ),
+            TestCase(
+                Query::new().filter("current_state < 1".parse().unwrap()),
+                vec!["Current Load", "Current Users", "Root Partition"],
+            ),
+            // Critical before Warning, though "Warning" sorts after "Critical"
+            TestCase(
+                Query::new()
+                    .filter("current_state != Ok".parse().unwrap())
+                    .sort_by_desc("current_state")
+                    .sort_by("state_type"),
+                vec!["Swap Usage", "HTTP", "Total Processes", "PING"],
+            ),
+            TestCase(
+                Query::new().sort_by("current_state").limit(4),
+                vec!["Current Load", "Current Users", "Root Partition", "PING"],
+            ),
+        ];
+        for test_case in test_cases {
+            assert_eq!(
+                descriptions(status.query_services(&test_case.0)),
+                test_case.1
+            );
+        }
+    }
+
+    #[test]
+    fn test_query_hosts() {
+        let status = status();
+        let query = Query::new().filter(
+            "current_state == Up && host_name =~ 'local'"
+                .parse()
+                .unwrap(),
+        );
+        assert_eq!(status.query_hosts(&query).len(), 1);
+        let query = Query::new().filter(Filter::eq("current_state", HostState::Down));
+        assert!(status.query_hosts(&query).is_empty());
+    }
+}
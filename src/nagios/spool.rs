@@ -0,0 +1,247 @@
+use chrono::{DateTime, Utc};
+use std::ffi::{CString, OsStr};
+use std::fs::File;
+use std::io::{self, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::FromRawFd;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+use super::object::CheckType;
+
+////////////////////////////////////
+// error
+
+#[derive(Error, Debug)]
+pub enum SpoolError {
+    #[error("check result path is not a directory: {0}")]
+    NotADirectory(PathBuf),
+    #[error("invalid check result path: {0}")]
+    InvalidPath(PathBuf),
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+}
+
+////////////////////////////////////
+// check result
+
+/// A check result to be picked up by the Nagios reaper.
+/// A `service_description` of `None` makes it a host check result.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CheckResult {
+    pub host_name: String,
+    pub service_description: Option<String>,
+    pub check_type: CheckType,
+    pub return_code: u32,
+    pub output: String,
+    pub start_time: DateTime<Utc>,
+    pub finish_time: DateTime<Utc>,
+}
+
+impl CheckResult {
+    fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        match &self.service_description {
+            Some(_) => writeln!(writer, "### Nagios Service Check Result ###")?,
+            None => writeln!(writer, "### Nagios Host Check Result ###")?,
+        }
+        writeln!(writer, "host_name={}", self.host_name)?;
+        if let Some(service_description) = &self.service_description {
+            writeln!(writer, "service_description={}", service_description)?;
+        }
+        writeln!(writer, "check_type={}", self.check_type.code())?;
+        writeln!(writer, "check_options=0")?;
+        writeln!(writer, "scheduled_check=0")?;
+        writeln!(writer, "reschedule_check=0")?;
+        writeln!(writer, "latency=0.000000")?;
+        writeln!(writer, "start_time={}", format_time(&self.start_time))?;
+        writeln!(writer, "finish_time={}", format_time(&self.finish_time))?;
+        writeln!(writer, "early_timeout=0")?;
+        writeln!(writer, "exited_ok=1")?;
+        writeln!(writer, "return_code={}", self.return_code)?;
+        writeln!(writer, "output={}", escape_output(&self.output))?;
+        writeln!(writer)?;
+        Ok(())
+    }
+}
+
+fn format_time(time: &DateTime<Utc>) -> String {
+    format!("{}.{:06}", time.timestamp(), time.timestamp_subsec_micros())
+}
+
+/// Nagios reads one line per value, so newlines in the output are escaped
+/// the same way Nagios escapes them when it writes check results itself.
+fn escape_output(output: &str) -> String {
+    output
+        .replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('\r', "")
+}
+
+////////////////////////////////////
+// spool
+
+/// Writer for the Nagios `check_result_path` spool directory.
+///
+/// Each submission is written to a fresh `cXXXXXX` file and only marked
+/// ready for the reaper, by creating the `cXXXXXX.ok` file, once the result
+/// file is complete and synced to disk.
+#[derive(Debug, Clone)]
+pub struct CheckResultSpool {
+    check_result_path: PathBuf,
+}
+
+impl CheckResultSpool {
+    pub fn new<P: AsRef<Path>>(check_result_path: P) -> CheckResultSpool {
+        CheckResultSpool {
+            check_result_path: check_result_path.as_ref().to_path_buf(),
+        }
+    }
+
+    pub fn submit(&self, result: &CheckResult) -> Result<PathBuf, SpoolError> {
+        self.submit_all(std::slice::from_ref(result))
+    }
+
+    /// Writes all `results` into a single check result file and returns its path.
+    pub fn submit_all(&self, results: &[CheckResult]) -> Result<PathBuf, SpoolError> {
+        if !self.check_result_path.is_dir() {
+            return Err(SpoolError::NotADirectory(self.check_result_path.clone()));
+        }
+
+        let (mut file, path) = self.create_result_file()?;
+        let written = write_results(&mut file, results).and_then(|_| file.sync_all());
+        if let Err(error) = written {
+            let _ = std::fs::remove_file(&path);
+            return Err(error.into());
+        }
+
+        let mut ok_path = path.clone().into_os_string();
+        ok_path.push(".ok");
+        File::create(ok_path)?;
+
+        Ok(path)
+    }
+
+    fn create_result_file(&self) -> Result<(File, PathBuf), SpoolError> {
+        let template = self.check_result_path.join("cXXXXXX");
+        let mut template = CString::new(template.as_os_str().as_bytes())
+            .map_err(|_| SpoolError::InvalidPath(self.check_result_path.clone()))?
+            .into_bytes_with_nul();
+
+        let fd = unsafe { libc::mkstemp(template.as_mut_ptr() as *mut libc::c_char) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        let file = unsafe { File::from_raw_fd(fd) };
+
+        template.pop();
+        let path = PathBuf::from(OsStr::from_bytes(&template));
+        Ok((file, path))
+    }
+}
+
+fn write_results<W: Write>(writer: &mut W, results: &[CheckResult]) -> io::Result<()> {
+    let mut buf = Vec::new();
+    writeln!(buf, "### Passive Check Result File ###")?;
+    writeln!(buf, "file_time={}", Utc::now().timestamp())?;
+    writeln!(buf)?;
+    for result in results {
+        result.write_to(&mut buf)?;
+    }
+    writer.write_all(&buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn check_result(service_description: Option<&str>, output: &str) -> CheckResult {
+        CheckResult {
+            host_name: "localhost".to_string(),
+            service_description: service_description.map(|s| s.to_string()),
+            check_type: CheckType::Passive,
+            return_code: 2,
+            output: output.to_string(),
+            start_time: Utc.timestamp_opt(1647775378, 250000000).unwrap(),
+            finish_time: Utc.timestamp_opt(1647775379, 0).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_write_to() {
+        struct TestCase(CheckResult, &'static str);
+        let test_cases = vec![
+            TestCase(
+                check_result(Some("Current Load"), "CRITICAL - load average: 9.00"),
+                "### Nagios Service Check Result ###\n\
+                 host_name=localhost\n\
+                 service_description=Current Load\n\
+                 check_type=1\n\
+                 check_options=0\n\
+                 scheduled_check=0\n\
+                 reschedule_check=0\n\
+                 latency=0.000000\n\
+                 start_time=1647775378.250000\n\
+                 finish_time=1647775379.000000\n\
+                 early_timeout=0\n\
+                 exited_ok=1\n\
+                 return_code=2\n\
+                 output=CRITICAL - load average: 9.00\n\n",
+            ),
+            TestCase(
+                check_result(None, "DOWN\nno route to host C:\\"),
+                "### Nagios Host Check Result ###\n\
+                 host_name=localhost\n\
+                 check_type=1\n\
+                 check_options=0\n\
+                 scheduled_check=0\n\
+                 reschedule_check=0\n\
+                 latency=0.000000\n\
+                 start_time=1647775378.250000\n\
+                 finish_time=1647775379.000000\n\
+                 early_timeout=0\n\
+                 exited_ok=1\n\
+                 return_code=2\n\
+                 output=DOWN\\nno route to host C:\\\\\n\n",
+            ),
+        ];
+
+        for test_case in test_cases {
+            let mut buf = Vec::new();
+            test_case.0.write_to(&mut buf).unwrap();
+            assert_eq!(String::from_utf8(buf).unwrap(), test_case.1);
+        }
+    }
+
+    #[test]
+    fn test_submit() {
+        let dir = tempfile::tempdir().unwrap();
+        let spool = CheckResultSpool::new(dir.path());
+
+        let path = spool
+            .submit_all(&[
+                check_result(Some("Current Load"), "CRITICAL"),
+                check_result(Some("PING"), "OK"),
+            ])
+            .unwrap();
+
+        let file_name = path.file_name().unwrap().to_str().unwrap();
+        assert_eq!(file_name.len(), 7);
+        assert!(file_name.starts_with('c'));
+        assert!(dir.path().join(format!("{}.ok", file_name)).exists());
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(content.starts_with("### Passive Check Result File ###\nfile_time="));
+        assert_eq!(content.matches("host_name=localhost\n").count(), 2);
+        assert!(content.contains("service_description=PING\n"));
+    }
+
+    #[test]
+    fn test_submit_not_a_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let spool = CheckResultSpool::new(dir.path().join("missing"));
+
+        let result = spool.submit(&check_result(None, "UP"));
+        assert!(matches!(result, Err(SpoolError::NotADirectory(_))));
+    }
+}
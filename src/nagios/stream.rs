@@ -0,0 +1,189 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::ops::ControlFlow;
+use std::path::Path;
+use thiserror::Error;
+
+use super::block::{Block, BlockType, ParseError};
+use super::object::{Comment, ConvertError, Downtime, Host, KeyValues, Service};
+
+#[derive(Error, Debug)]
+pub enum StreamError {
+    #[error("{0}")]
+    Parse(String),
+    #[error("{0}")]
+    Convert(#[from] ConvertError),
+}
+
+impl From<ParseError> for StreamError {
+    fn from(error: ParseError) -> Self {
+        StreamError::Parse(error.to_string())
+    }
+}
+
+/// One block of status.dat, converted to its type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StatusObject {
+    Info(HashMap<String, String>),
+    Program(HashMap<String, String>),
+    Host(Host),
+    Service(Service),
+    Contact(HashMap<String, String>),
+    Comment(Comment),
+    Downtime(Downtime),
+}
+
+impl StatusObject {
+    /// `None` for blocks nagrs does not know.
+    pub(super) fn from_key_values<K: KeyValues>(
+        block_type: &BlockType,
+        key_values: K,
+    ) -> Result<Option<StatusObject>, ConvertError> {
+        let object = match block_type {
+            BlockType::Info => StatusObject::Info(key_values.into_map()),
+            BlockType::Program => StatusObject::Program(key_values.into_map()),
+            BlockType::Host => StatusObject::Host(Host::from_key_values(&key_values)?),
+            BlockType::Service => StatusObject::Service(Service::from_key_values(&key_values)?),
+            BlockType::Contact => StatusObject::Contact(key_values.into_map()),
+            BlockType::HostComment | BlockType::ServiceComment => {
+                StatusObject::Comment(Comment::from_key_values(&key_values)?)
+            }
+            BlockType::HostDowntime | BlockType::ServiceDowntime => {
+                StatusObject::Downtime(Downtime::from_key_values(&key_values)?)
+            }
+            BlockType::Unkown => return Ok(None),
+        };
+        Ok(Some(object))
+    }
+}
+
+/// Reads status.dat one object at a time, in file order, holding no more
+/// than the current block in memory.
+pub struct StatusObjects<'a> {
+    blocks: Box<dyn Iterator<Item = Result<Block, ParseError>> + 'a>,
+}
+
+impl<'a> StatusObjects<'a> {
+    pub fn new<R: Read + 'a>(reader: R) -> StatusObjects<'a> {
+        StatusObjects {
+            blocks: Box::new(Block::to_blocks(io::BufReader::new(reader))),
+        }
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<StatusObjects<'static>> {
+        Ok(StatusObjects::new(File::open(path)?))
+    }
+
+    /// Calls `visit` with each object until it returns `ControlFlow::Break`
+    /// or the first error.
+    pub fn visit<F>(self, mut visit: F) -> Result<(), StreamError>
+    where
+        F: FnMut(StatusObject) -> ControlFlow<()>,
+    {
+        for object in self {
+            if visit(object?).is_break() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Iterator for StatusObjects<'_> {
+    type Item = Result<StatusObject, StreamError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let object = match self.blocks.next()? {
+                Ok(block) => StatusObject::from_key_values(&block.block_type, block.key_values),
+                Err(error) => return Some(Err(error.into())),
+            };
+            match object {
+                Ok(Some(object)) => return Some(Ok(object)),
+                Ok(None) => continue,
+                Err(error) => return Some(Err(error.into())),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_objects() {
+        let objects = StatusObjects::open("testdata/status.dat")
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let kinds = objects
+            .iter()
+            .map(|object| match object {
+                StatusObject::Info(_) => "info",
+                StatusObject::Program(_) => "program",
+                StatusObject::Host(_) => "host",
+                StatusObject::Service(_) => "service",
+                StatusObject::Contact(_) => "contact",
+                StatusObject::Comment(_) => "comment",
+                StatusObject::Downtime(_) => "downtime",
+            })
+            .collect::<Vec<_>>();
+        struct TestCase<'a>(&'a str, usize);
+        let test_cases = vec![
+            TestCase("info", 1),
+            TestCase("program", 1),
+            TestCase("host", 1),
+            TestCase("service", 7),
+            TestCase("contact", 1),
+            TestCase("comment", 2),
+            TestCase("downtime", 1),
+        ];
+        for test_case in test_cases {
+            assert_eq!(
+                kinds.iter().filter(|kind| **kind == test_case.0).count(),
+                test_case.1,
+                "{}",
+                test_case.0
+            );
+        }
+        assert_eq!(kinds[0], "info");
+    }
+
+    #[test]
+    fn test_visit() {
+        let mut services = Vec::new();
+        StatusObjects::open("testdata/status.dat")
+            .unwrap()
+            .visit(|object| {
+                if let StatusObject::Service(service) = object {
+                    services.push(service.service_description);
+                    if services.len() == 2 {
+                        return ControlFlow::Break(());
+                    }
+                }
+                ControlFlow::Continue(())
+            })
+            .unwrap();
+        assert_eq!(services, vec!["Current Load", "Current Users"]);
+    }
+
+    #[test]
+    fn test_errors() {
+        struct TestCase<'a>(&'a str, &'a str);
+        let test_cases = vec![
+            TestCase("hoststatus {\nbroken\n}\n", "invalid key value: broken"),
+            TestCase(
+                "hoststatus {\nhost_name=localhost\n}\n",
+                "key does not exists: modified_attributes",
+            ),
+        ];
+        for test_case in test_cases {
+            let error = StatusObjects::new(test_case.0.as_bytes())
+                .visit(|_| ControlFlow::Continue(()))
+                .unwrap_err();
+            assert_eq!(error.to_string(), test_case.1);
+        }
+    }
+}
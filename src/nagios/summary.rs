@@ -0,0 +1,209 @@
+use serde::Serialize;
+
+use super::object::{HostState, ServiceState};
+use super::NagiosStatus;
+
+/// Problems in one state, split by whether someone is already on them.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ProblemCount {
+    pub handled: usize,
+    pub unhandled: usize,
+}
+
+impl ProblemCount {
+    pub fn total(&self) -> usize {
+        self.handled + self.unhandled
+    }
+
+    fn add(&mut self, handled: bool) {
+        if handled {
+            self.handled += 1;
+        } else {
+            self.unhandled += 1;
+        }
+    }
+}
+
+/// Flapping and disabled counts, over all objects regardless of state.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct FeatureCount {
+    pub flapping: usize,
+    pub checks_disabled: usize,
+    pub notifications_disabled: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct HostSummary {
+    /// Hosts that have not been checked yet, counted in no state.
+    pub pending: usize,
+    pub up: usize,
+    pub down: ProblemCount,
+    pub unreachable: ProblemCount,
+    pub features: FeatureCount,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ServiceSummary {
+    /// Services that have not been checked yet, counted in no state.
+    pub pending: usize,
+    pub ok: usize,
+    pub warning: ProblemCount,
+    pub critical: ProblemCount,
+    pub unknown: ProblemCount,
+    pub features: FeatureCount,
+}
+
+/// The numbers of the Nagios tactical overview.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Summary {
+    pub hosts: HostSummary,
+    pub services: ServiceSummary,
+}
+
+impl FeatureCount {
+    fn add(&mut self, is_flapping: bool, checks_enabled: bool, notifications_enabled: bool) {
+        self.flapping += usize::from(is_flapping);
+        self.checks_disabled += usize::from(!checks_enabled);
+        self.notifications_disabled += usize::from(!notifications_enabled);
+    }
+}
+
+impl NagiosStatus {
+    /// Counts hosts and services by state, problems split by
+    /// `ProblemClass::is_handled`.
+    pub fn summary(&self) -> Summary {
+        let mut summary = Summary::default();
+
+        for host in self.hosts.values() {
+            let hosts = &mut summary.hosts;
+            hosts.features.add(
+                host.is_flapping,
+                host.active_checks_enabled,
+                host.notifications_enabled,
+            );
+            if !host.has_been_checked {
+                hosts.pending += 1;
+                continue;
+            }
+            let handled = host
+                .problem_class()
+                .is_some_and(|problem_class| problem_class.is_handled());
+            match host.current_state {
+                HostState::Up => hosts.up += 1,
+                HostState::Down => hosts.down.add(handled),
+                HostState::Unreachable => hosts.unreachable.add(handled),
+            }
+        }
+
+        for service in self.services.values().flatten() {
+            let services = &mut summary.services;
+            services.features.add(
+                service.is_flapping,
+                service.active_checks_enabled,
+                service.notifications_enabled,
+            );
+            if !service.has_been_checked {
+                services.pending += 1;
+                continue;
+            }
+            let handled = service
+                .problem_class(self.hosts.get(&service.host_name))
+                .is_some_and(|problem_class| problem_class.is_handled());
+            match service.current_state {
+                ServiceState::Ok => services.ok += 1,
+                ServiceState::Warning => services.warning.add(handled),
+                ServiceState::Critical => services.critical.add(handled),
+                ServiceState::Unknown => services.unknown.add(handled),
+            }
+        }
+
+        summary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary() {
+        let mut status = NagiosStatus::parse_file("testdata/status.dat").unwrap();
+        let summary = status.summary();
+        assert_eq!((summary.services.ok, summary.services.pending), (1, 6));
+
+        for service in status.services.get_mut("localhost").unwrap() {
+            service.has_been_checked = true;
+            match service.service_description.as_str() {
+                "HTTP" => service.current_state = ServiceState::Critical,
+                "PING" => {
+                    service.current_state = ServiceState::Critical;
+                    service.problem_has_been_acknowledged = true;
+                }
+                "Swap Usage" => {
+                    service.current_state = ServiceState::Warning;
+                    service.scheduled_downtime_depth = 1;
+                    service.is_flapping = true;
+                }
+                "Total Processes" => {
+                    service.has_been_checked = false;
+                    service.active_checks_enabled = false;
+                    service.notifications_enabled = false;
+                }
+                _ => {}
+            }
+        }
+
+        let summary = status.summary();
+        assert_eq!(
+            summary.services,
+            ServiceSummary {
+                pending: 1,
+                ok: 3,
+                warning: ProblemCount {
+                    handled: 1,
+                    unhandled: 0,
+                },
+                critical: ProblemCount {
+                    handled: 1,
+                    unhandled: 1,
+                },
+                unknown: ProblemCount::default(),
+                features: FeatureCount {
+                    flapping: 1,
+                    checks_disabled: 1,
+                    // HTTP has notifications disabled in testdata
+                    notifications_disabled: 2,
+                },
+            }
+        );
+        assert_eq!(summary.hosts.up, 1);
+        assert_eq!(summary.hosts.down.total(), 0);
+    }
+
+    #[test]
+    fn test_summary_host_down() {
+        let mut status = NagiosStatus::parse_file("testdata/status.dat").unwrap();
+        status.hosts.get_mut("localhost").unwrap().current_state = HostState::Down;
+        for service in status.services.get_mut("localhost").unwrap() {
+            service.has_been_checked = true;
+            service.current_state = ServiceState::Critical;
+        }
+
+        let summary = status.summary();
+        assert_eq!(
+            summary.hosts.down,
+            ProblemCount {
+                handled: 0,
+                unhandled: 1,
+            }
+        );
+        // services of a down host are handled
+        assert_eq!(
+            summary.services.critical,
+            ProblemCount {
+                handled: 7,
+                unhandled: 0,
+            }
+        );
+    }
+}
@@ -0,0 +1,283 @@
+use std::collections::HashSet;
+
+use super::cmd::{
+    DisableHostCheck, DisableHostEventHandler, DisableHostFlapDetection, DisableHostNotifications,
+    DisableSvcCheck, DisableSvcEventHandler, DisableSvcFlapDetection, DisableSvcNotifications,
+    EnableHostCheck, EnableHostEventHandler, EnableHostFlapDetection, EnableHostNotifications,
+    EnableSvcCheck, EnableSvcEventHandler, EnableSvcFlapDetection, EnableSvcNotifications,
+    NagiosCmd,
+};
+use super::object::{Host, Service};
+use super::NagiosStatus;
+
+impl Host {
+    /// Commands that restore the host's current active checks, notifications,
+    /// event handler and flap detection toggles.
+    pub fn restore_cmds(&self) -> Vec<Box<dyn NagiosCmd>> {
+        let host_name = || self.host_name.clone();
+        vec![
+            if self.active_checks_enabled {
+                Box::new(EnableHostCheck {
+                    host_name: host_name(),
+                }) as Box<dyn NagiosCmd>
+            } else {
+                Box::new(DisableHostCheck {
+                    host_name: host_name(),
+                })
+            },
+            if self.notifications_enabled {
+                Box::new(EnableHostNotifications {
+                    host_name: host_name(),
+                })
+            } else {
+                Box::new(DisableHostNotifications {
+                    host_name: host_name(),
+                })
+            },
+            if self.event_handler_enabled {
+                Box::new(EnableHostEventHandler {
+                    host_name: host_name(),
+                })
+            } else {
+                Box::new(DisableHostEventHandler {
+                    host_name: host_name(),
+                })
+            },
+            if self.flap_detection_enabled {
+                Box::new(EnableHostFlapDetection {
+                    host_name: host_name(),
+                })
+            } else {
+                Box::new(DisableHostFlapDetection {
+                    host_name: host_name(),
+                })
+            },
+        ]
+    }
+}
+
+impl Service {
+    /// Commands that restore the service's current active checks,
+    /// notifications, event handler and flap detection toggles.
+    pub fn restore_cmds(&self) -> Vec<Box<dyn NagiosCmd>> {
+        let host_name = || self.host_name.clone();
+        let service_description = || self.service_description.clone();
+        vec![
+            if self.active_checks_enabled {
+                Box::new(EnableSvcCheck {
+                    host_name: host_name(),
+                    service_description: service_description(),
+                }) as Box<dyn NagiosCmd>
+            } else {
+                Box::new(DisableSvcCheck {
+                    host_name: host_name(),
+                    service_description: service_description(),
+                })
+            },
+            if self.notifications_enabled {
+                Box::new(EnableSvcNotifications {
+                    host_name: host_name(),
+                    service_description: service_description(),
+                })
+            } else {
+                Box::new(DisableSvcNotifications {
+                    host_name: host_name(),
+                    service_description: service_description(),
+                })
+            },
+            if self.event_handler_enabled {
+                Box::new(EnableSvcEventHandler {
+                    host_name: host_name(),
+                    service_description: service_description(),
+                })
+            } else {
+                Box::new(DisableSvcEventHandler {
+                    host_name: host_name(),
+                    service_description: service_description(),
+                })
+            },
+            if self.flap_detection_enabled {
+                Box::new(EnableSvcFlapDetection {
+                    host_name: host_name(),
+                    service_description: service_description(),
+                })
+            } else {
+                Box::new(DisableSvcFlapDetection {
+                    host_name: host_name(),
+                    service_description: service_description(),
+                })
+            },
+        ]
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Target<'a> {
+    Host(&'a Host),
+    Service(&'a Service),
+}
+
+/// Computes the batch that undoes `cmds`, by restoring the toggles of every
+/// host and service they touch to their state in `snapshot`.
+///
+/// Commands addressing a hostgroup are not covered, since status.dat does not
+/// record group membership.
+pub fn undo_cmds(snapshot: &NagiosStatus, cmds: &[Box<dyn NagiosCmd>]) -> Vec<Box<dyn NagiosCmd>> {
+    let mut targets: Vec<Target> = Vec::new();
+    let mut seen: HashSet<(&str, Option<&str>)> = HashSet::new();
+    for cmd in cmds {
+        let host_name = match cmd.arg("host_name") {
+            Some(host_name) => host_name,
+            None => continue,
+        };
+        let services = snapshot
+            .services
+            .get(&host_name)
+            .map(|services| services.iter());
+
+        let cmd_targets: Vec<Target> = match cmd.arg("service_description") {
+            Some(service_description) => services
+                .into_iter()
+                .flatten()
+                .filter(|service| service.service_description == service_description)
+                .map(Target::Service)
+                .collect(),
+            None if cmd.name().contains("_HOST_SVC_") => services
+                .into_iter()
+                .flatten()
+                .map(Target::Service)
+                .collect(),
+            None => snapshot
+                .hosts
+                .get(&host_name)
+                .map(Target::Host)
+                .into_iter()
+                .collect(),
+        };
+
+        for target in cmd_targets {
+            let key = match target {
+                Target::Host(host) => (host.host_name.as_str(), None),
+                Target::Service(service) => (
+                    service.host_name.as_str(),
+                    Some(service.service_description.as_str()),
+                ),
+            };
+            if seen.insert(key) {
+                targets.push(target);
+            }
+        }
+    }
+
+    targets
+        .into_iter()
+        .flat_map(|target| match target {
+            Target::Host(host) => host.restore_cmds(),
+            Target::Service(service) => service.restore_cmds(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nagios::cmd::{DisableHostSvcChecks, DisableHostgroupHostChecks};
+
+    fn snapshot() -> NagiosStatus {
+        NagiosStatus::parse_file("testdata/status.dat").unwrap()
+    }
+
+    fn cmd_strings(cmds: &[Box<dyn NagiosCmd>]) -> Vec<String> {
+        cmds.iter().map(|cmd| cmd.to_cmd_string()).collect()
+    }
+
+    #[test]
+    fn test_host_restore_cmds() {
+        let mut host = snapshot().get_host("localhost").unwrap();
+        host.notifications_enabled = false;
+        host.flap_detection_enabled = false;
+        assert_eq!(
+            cmd_strings(&host.restore_cmds()),
+            vec![
+                "ENABLE_HOST_CHECK;localhost",
+                "DISABLE_HOST_NOTIFICATIONS;localhost",
+                "ENABLE_HOST_EVENT_HANDLER;localhost",
+                "DISABLE_HOST_FLAP_DETECTION;localhost",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_service_restore_cmds() {
+        let mut service = snapshot().get_host_services("localhost").unwrap()[0].clone();
+        service.active_checks_enabled = false;
+        service.event_handler_enabled = false;
+        let service_description = service.service_description.clone();
+        assert_eq!(
+            cmd_strings(&service.restore_cmds()),
+            vec![
+                format!("DISABLE_SVC_CHECK;localhost;{}", service_description),
+                format!("ENABLE_SVC_NOTIFICATIONS;localhost;{}", service_description),
+                format!(
+                    "DISABLE_SVC_EVENT_HANDLER;localhost;{}",
+                    service_description
+                ),
+                format!(
+                    "ENABLE_SVC_FLAP_DETECTION;localhost;{}",
+                    service_description
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_undo_cmds() {
+        let snapshot = snapshot();
+        let service_count = snapshot.get_host_services("localhost").unwrap().len();
+
+        struct TestCase(Vec<Box<dyn NagiosCmd>>, usize);
+        let test_cases = vec![
+            TestCase(
+                vec![
+                    Box::new(DisableHostCheck {
+                        host_name: "localhost".to_string(),
+                    }),
+                    // the same host is only restored once
+                    Box::new(DisableHostNotifications {
+                        host_name: "localhost".to_string(),
+                    }),
+                ],
+                4,
+            ),
+            TestCase(
+                vec![Box::new(DisableSvcCheck {
+                    host_name: "localhost".to_string(),
+                    service_description: "PING".to_string(),
+                })],
+                4,
+            ),
+            TestCase(
+                vec![Box::new(DisableHostSvcChecks {
+                    host_name: "localhost".to_string(),
+                })],
+                4 * service_count,
+            ),
+            TestCase(
+                vec![Box::new(DisableHostCheck {
+                    host_name: "unknown".to_string(),
+                })],
+                0,
+            ),
+            TestCase(
+                vec![Box::new(DisableHostgroupHostChecks {
+                    hostgroup_name: "linux-servers".to_string(),
+                })],
+                0,
+            ),
+        ];
+
+        for test_case in test_cases {
+            assert_eq!(undo_cmds(&snapshot, &test_case.0).len(), test_case.1);
+        }
+    }
+}
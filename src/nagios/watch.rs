@@ -0,0 +1,445 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fmt;
+use std::ops::ControlFlow;
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use super::cache::StatusCache;
+use super::object::{Host, HostState, Service, ServiceState, StateType};
+use super::{Matched, NagiosStatus};
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum Change {
+    Added,
+    Removed,
+    /// The state or the state type changed, e.g. a soft critical became hard.
+    HostStateChanged {
+        from: HostState,
+        from_type: StateType,
+        to: HostState,
+        to_type: StateType,
+    },
+    ServiceStateChanged {
+        from: ServiceState,
+        from_type: StateType,
+        to: ServiceState,
+        to_type: StateType,
+    },
+    Acknowledged,
+    Unacknowledged,
+    DowntimeStarted,
+    DowntimeEnded,
+    FlappingStarted,
+    FlappingStopped,
+    NotificationsEnabled,
+    NotificationsDisabled,
+}
+
+/// A change of one host or service between two snapshots.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ChangeEvent {
+    pub host_name: String,
+    /// `None` for host events.
+    pub service_description: Option<String>,
+    pub change: Change,
+}
+
+impl fmt::Display for ChangeEvent {
+    /// e.g. `localhost/HTTP: OK (HARD) -> CRITICAL (SOFT)`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.host_name)?;
+        if let Some(service_description) = &self.service_description {
+            write!(f, "/{}", service_description)?;
+        }
+        let state_change = |from: &dyn fmt::Debug, from_type, to: &dyn fmt::Debug, to_type| {
+            format!(
+                "{} ({}) -> {} ({})",
+                format!("{:?}", from).to_uppercase(),
+                format!("{:?}", from_type).to_uppercase(),
+                format!("{:?}", to).to_uppercase(),
+                format!("{:?}", to_type).to_uppercase(),
+            )
+        };
+        let change = match &self.change {
+            Change::Added => "added".to_string(),
+            Change::Removed => "removed".to_string(),
+            Change::HostStateChanged {
+                from,
+                from_type,
+                to,
+                to_type,
+            } => state_change(from, from_type, to, to_type),
+            Change::ServiceStateChanged {
+                from,
+                from_type,
+                to,
+                to_type,
+            } => state_change(from, from_type, to, to_type),
+            Change::Acknowledged => "acknowledged".to_string(),
+            Change::Unacknowledged => "acknowledgement removed".to_string(),
+            Change::DowntimeStarted => "downtime started".to_string(),
+            Change::DowntimeEnded => "downtime ended".to_string(),
+            Change::FlappingStarted => "started flapping".to_string(),
+            Change::FlappingStopped => "stopped flapping".to_string(),
+            Change::NotificationsEnabled => "notifications enabled".to_string(),
+            Change::NotificationsDisabled => "notifications disabled".to_string(),
+        };
+        write!(f, ": {}", change)
+    }
+}
+
+/// The attributes hosts and services share that produce events.
+struct Flags {
+    acknowledged: bool,
+    in_downtime: bool,
+    flapping: bool,
+    notifications_enabled: bool,
+}
+
+impl From<&Host> for Flags {
+    fn from(host: &Host) -> Self {
+        Flags {
+            acknowledged: host.problem_has_been_acknowledged,
+            in_downtime: host.scheduled_downtime_depth > 0,
+            flapping: host.is_flapping,
+            notifications_enabled: host.notifications_enabled,
+        }
+    }
+}
+
+impl From<&Service> for Flags {
+    fn from(service: &Service) -> Self {
+        Flags {
+            acknowledged: service.problem_has_been_acknowledged,
+            in_downtime: service.scheduled_downtime_depth > 0,
+            flapping: service.is_flapping,
+            notifications_enabled: service.notifications_enabled,
+        }
+    }
+}
+
+fn flag_changes(old: Flags, new: Flags, changes: &mut Vec<Change>) {
+    let toggles = [
+        (
+            old.acknowledged,
+            new.acknowledged,
+            Change::Acknowledged,
+            Change::Unacknowledged,
+        ),
+        (
+            old.in_downtime,
+            new.in_downtime,
+            Change::DowntimeStarted,
+            Change::DowntimeEnded,
+        ),
+        (
+            old.flapping,
+            new.flapping,
+            Change::FlappingStarted,
+            Change::FlappingStopped,
+        ),
+        (
+            old.notifications_enabled,
+            new.notifications_enabled,
+            Change::NotificationsEnabled,
+            Change::NotificationsDisabled,
+        ),
+    ];
+    for (old, new, on, off) in toggles {
+        match (old, new) {
+            (false, true) => changes.push(on),
+            (true, false) => changes.push(off),
+            _ => {}
+        }
+    }
+}
+
+fn host_changes(old: &Host, new: &Host) -> Vec<Change> {
+    let mut changes = Vec::new();
+    if (&old.current_state, &old.state_type) != (&new.current_state, &new.state_type) {
+        changes.push(Change::HostStateChanged {
+            from: old.current_state.clone(),
+            from_type: old.state_type.clone(),
+            to: new.current_state.clone(),
+            to_type: new.state_type.clone(),
+        });
+    }
+    flag_changes(old.into(), new.into(), &mut changes);
+    changes
+}
+
+fn service_changes(old: &Service, new: &Service) -> Vec<Change> {
+    let mut changes = Vec::new();
+    if (&old.current_state, &old.state_type) != (&new.current_state, &new.state_type) {
+        changes.push(Change::ServiceStateChanged {
+            from: old.current_state.clone(),
+            from_type: old.state_type.clone(),
+            to: new.current_state.clone(),
+            to_type: new.state_type.clone(),
+        });
+    }
+    flag_changes(old.into(), new.into(), &mut changes);
+    changes
+}
+
+fn object_changes<T>(
+    old: Option<&T>,
+    new: Option<&T>,
+    changes: fn(&T, &T) -> Vec<Change>,
+) -> Vec<Change> {
+    match (old, new) {
+        (None, None) => vec![],
+        (None, Some(_)) => vec![Change::Added],
+        (Some(_), None) => vec![Change::Removed],
+        (Some(old), Some(new)) => changes(old, new),
+    }
+}
+
+/// The events that lead from `old` to `new`, sorted by host name, each host
+/// before its services.
+pub fn changes(old: &NagiosStatus, new: &NagiosStatus) -> Vec<ChangeEvent> {
+    old.matched(new)
+        .flat_map(|matched| {
+            let (host_name, service_description, changes) = match matched {
+                Matched::Host {
+                    host_name,
+                    old,
+                    new,
+                } => (host_name, None, object_changes(old, new, host_changes)),
+                Matched::Service {
+                    host_name,
+                    service_description,
+                    old,
+                    new,
+                } => (
+                    host_name,
+                    Some(service_description),
+                    object_changes(old, new, service_changes),
+                ),
+            };
+            changes.into_iter().map(move |change| ChangeEvent {
+                host_name: host_name.to_string(),
+                service_description: service_description.map(str::to_string),
+                change,
+            })
+        })
+        .collect()
+}
+
+/// Polls status.dat and reports what changed since the previous poll.
+#[derive(Debug)]
+pub struct Watcher {
+    cache: StatusCache,
+    last: Option<Arc<NagiosStatus>>,
+    /// Events found but not yet delivered, oldest first.
+    pending: VecDeque<ChangeEvent>,
+}
+
+impl Watcher {
+    pub fn new<P: AsRef<Path>>(path: P) -> Watcher {
+        Watcher {
+            cache: StatusCache::new(path),
+            last: None,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// The events since the previous poll, after any that `watch` left
+    /// undelivered. The first poll only records the current status and
+    /// reports nothing.
+    pub fn poll(&mut self) -> Result<Vec<ChangeEvent>> {
+        let events = self.poll_new()?;
+        let mut pending = Vec::from(std::mem::take(&mut self.pending));
+        pending.extend(events);
+        Ok(pending)
+    }
+
+    fn poll_new(&mut self) -> Result<Vec<ChangeEvent>> {
+        let status = self.cache.get()?;
+        let events = match &self.last {
+            Some(last) if Arc::ptr_eq(last, &status) => vec![],
+            Some(last) => changes(last, &status),
+            None => vec![],
+        };
+        self.last = Some(status);
+        Ok(events)
+    }
+
+    /// Polls every `interval` and calls `on_event` with each event until it
+    /// returns `ControlFlow::Break`. Events not delivered by then are kept
+    /// for the next `watch` or `poll`.
+    pub fn watch<F>(&mut self, interval: Duration, mut on_event: F) -> Result<()>
+    where
+        F: FnMut(&ChangeEvent) -> ControlFlow<()>,
+    {
+        loop {
+            let events = self.poll_new()?;
+            self.pending.extend(events);
+            while let Some(event) = self.pending.pop_front() {
+                if on_event(&event).is_break() {
+                    return Ok(());
+                }
+            }
+            thread::sleep(interval);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn status() -> NagiosStatus {
+        NagiosStatus::parse_file("testdata/status.dat").unwrap()
+    }
+
+    fn service_mut<'a>(status: &'a mut NagiosStatus, service_description: &str) -> &'a mut Service {
+        status
+            .services
+            .get_mut("localhost")
+            .unwrap()
+            .iter_mut()
+            .find(|service| service.service_description == service_description)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_changes() {
+        let old = status();
+        let mut new = status();
+        assert_eq!(changes(&old, &new), vec![]);
+
+        let host = new.hosts.get_mut("localhost").unwrap();
+        host.current_state = HostState::Down;
+        host.state_type = StateType::Soft;
+        host.problem_has_been_acknowledged = true;
+        service_mut(&mut new, "HTTP").notifications_enabled = true;
+        let ping = service_mut(&mut new, "PING");
+        ping.current_state = ServiceState::Critical;
+        ping.scheduled_downtime_depth = 1;
+        ping.is_flapping = true;
+        new.services
+            .get_mut("localhost")
+            .unwrap()
+            .retain(|service| service.service_description != "Swap Usage");
+        new.reindex();
+
+        let events = changes(&old, &new);
+        assert_eq!(
+            events
+                .iter()
+                .map(|event| event.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "localhost: UP (HARD) -> DOWN (SOFT)",
+                "localhost: acknowledged",
+                "localhost/HTTP: notifications enabled",
+                "localhost/PING: OK (HARD) -> CRITICAL (HARD)",
+                "localhost/PING: downtime started",
+                "localhost/PING: started flapping",
+                "localhost/Swap Usage: removed",
+            ]
+        );
+        assert_eq!(
+            events[0].change,
+            Change::HostStateChanged {
+                from: HostState::Up,
+                from_type: StateType::Hard,
+                to: HostState::Down,
+                to_type: StateType::Soft,
+            }
+        );
+
+        // the reverse removes what was added
+        let events = changes(&new, &old);
+        assert_eq!(events[1].change, Change::Unacknowledged);
+        assert_eq!(
+            events.last().unwrap().to_string(),
+            "localhost/Swap Usage: added"
+        );
+    }
+
+    #[test]
+    fn test_soft_to_hard() {
+        let mut old = status();
+        let mut new = status();
+        for (status, state_type) in [(&mut old, StateType::Soft), (&mut new, StateType::Hard)] {
+            let service = service_mut(status, "HTTP");
+            service.current_state = ServiceState::Critical;
+            service.state_type = state_type;
+        }
+        assert_eq!(
+            changes(&old, &new)
+                .iter()
+                .map(|event| event.to_string())
+                .collect::<Vec<_>>(),
+            vec!["localhost/HTTP: CRITICAL (SOFT) -> CRITICAL (HARD)"]
+        );
+    }
+
+    #[test]
+    fn test_poll() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("status.dat");
+        fs::copy("testdata/status.dat", &path).unwrap();
+        let mut watcher = Watcher::new(&path);
+        assert_eq!(watcher.poll().unwrap(), vec![]);
+        assert_eq!(watcher.poll().unwrap(), vec![]);
+
+        let updated = fs::read_to_string(&path).unwrap().replacen(
+            "problem_has_been_acknowledged=0",
+            "problem_has_been_acknowledged=1",
+            1,
+        );
+        let tmp_path = dir.path().join("status.dat.tmp");
+        fs::write(&tmp_path, updated).unwrap();
+        fs::rename(&tmp_path, &path).unwrap();
+
+        let mut events = Vec::new();
+        watcher
+            .watch(Duration::from_millis(10), |event| {
+                events.push(event.to_string());
+                ControlFlow::Break(())
+            })
+            .unwrap();
+        assert_eq!(events, vec!["localhost: acknowledged"]);
+    }
+
+    #[test]
+    fn test_watch_keeps_undelivered() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("status.dat");
+        fs::copy("testdata/status.dat", &path).unwrap();
+        let mut watcher = Watcher::new(&path);
+        watcher.poll().unwrap();
+
+        let updated = fs::read_to_string(&path)
+            .unwrap()
+            .replace("notifications_enabled=1", "notifications_enabled=0");
+        let tmp_path = dir.path().join("status.dat.tmp");
+        fs::write(&tmp_path, updated).unwrap();
+        fs::rename(&tmp_path, &path).unwrap();
+
+        let mut first = Vec::new();
+        watcher
+            .watch(Duration::from_millis(10), |event| {
+                first.push(event.to_string());
+                ControlFlow::Break(())
+            })
+            .unwrap();
+        assert_eq!(first, vec!["localhost: notifications disabled"]);
+
+        // the rest of that poll is delivered next, before anything new
+        let rest = watcher.poll().unwrap();
+        assert_eq!(rest.len(), 6);
+        assert!(rest
+            .iter()
+            .all(|event| event.change == Change::NotificationsDisabled));
+        assert!(watcher.poll().unwrap().is_empty());
+    }
+}
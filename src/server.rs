@@ -0,0 +1,564 @@
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::nagios::cache::StatusCache;
+use crate::nagios::cmd::{NagiosCmd, RawCmd};
+use crate::nagios::livestatus::{LqlError, LqlQuery};
+use crate::nagios::NagiosStatus;
+use crate::Nagrs;
+
+/// Time a client may take to send the next line before its connection is
+/// closed.
+pub const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Connections served at once; further clients wait to be accepted.
+pub const DEFAULT_MAX_CONNECTIONS: usize = 64;
+
+/// Longest request accepted, in bytes.
+const MAX_REQUEST_LEN: u64 = 1024 * 1024;
+
+type ErrorHandler = Box<dyn Fn(&io::Error) + Send + Sync>;
+
+/// Answers Livestatus requests on a Unix socket from status.dat, for
+/// installations where the Livestatus NEB module cannot be loaded.
+///
+/// `GET` requests are evaluated against status.dat, re-read whenever it
+/// changes. `COMMAND [timestamp] NAME;args` lines are written through
+/// `Nagrs::write_cmds`, so its policy, audit log and dry-run mode apply; the
+/// client's timestamp is replaced by the time of writing. Commands that
+/// cannot be parsed or written are answered with one error line each.
+pub struct LivestatusServer<P: AsRef<Path>> {
+    nagrs: Nagrs<P>,
+    socket_path: PathBuf,
+    status: StatusCache,
+    on_error: Option<ErrorHandler>,
+    read_timeout: Duration,
+    max_connections: usize,
+}
+
+impl<P: AsRef<Path> + Sync> LivestatusServer<P> {
+    pub fn new<S: AsRef<Path>>(nagrs: Nagrs<P>, socket_path: S) -> LivestatusServer<P> {
+        LivestatusServer {
+            status: StatusCache::new(nagrs.status_file_path.as_ref()),
+            nagrs,
+            socket_path: socket_path.as_ref().to_path_buf(),
+            on_error: None,
+            read_timeout: DEFAULT_READ_TIMEOUT,
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+        }
+    }
+
+    /// Maximum time to wait for a line from a client.
+    pub fn with_read_timeout(mut self, read_timeout: Duration) -> LivestatusServer<P> {
+        self.read_timeout = read_timeout;
+        self
+    }
+
+    /// Maximum number of connections served at once.
+    pub fn with_max_connections(mut self, max_connections: usize) -> LivestatusServer<P> {
+        self.max_connections = max_connections;
+        self
+    }
+
+    /// Calls `on_error` with each failed accept and each connection that
+    /// fails, which are otherwise ignored.
+    pub fn with_error_handler<F>(mut self, on_error: F) -> LivestatusServer<P>
+    where
+        F: Fn(&io::Error) + Send + Sync + 'static,
+    {
+        self.on_error = Some(Box::new(on_error));
+        self
+    }
+
+    /// Binds the socket, replacing a stale one, and serves forever.
+    pub fn run(&self) -> io::Result<()> {
+        let listener = self.bind()?;
+        self.serve(listener)
+    }
+
+    /// Binds `socket_path`. An existing socket is replaced; any other file
+    /// there is left alone and reported as `AlreadyExists`.
+    fn bind(&self) -> io::Result<UnixListener> {
+        match fs::symlink_metadata(&self.socket_path) {
+            Ok(metadata) if metadata.file_type().is_socket() => fs::remove_file(&self.socket_path)?,
+            Ok(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{} exists and is not a socket", self.socket_path.display()),
+                ))
+            }
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+            Err(error) => return Err(error),
+        }
+        UnixListener::bind(&self.socket_path)
+    }
+
+    /// Serves each connection of `listener` on its own thread, at most
+    /// `max_connections` at once. Failed accepts are passed to the error
+    /// handler and do not stop serving.
+    pub fn serve(&self, listener: UnixListener) -> io::Result<()> {
+        let active = &(Mutex::new(0), Condvar::new());
+        thread::scope(|scope| {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(error) => {
+                        self.report(&error);
+                        continue;
+                    }
+                };
+
+                let (count, freed) = active;
+                *freed
+                    .wait_while(count.lock().unwrap(), |count| {
+                        *count >= self.max_connections
+                    })
+                    .unwrap() += 1;
+
+                scope.spawn(move || {
+                    if let Err(error) = self.handle(stream) {
+                        self.report(&error);
+                    }
+                    *count.lock().unwrap() -= 1;
+                    freed.notify_one();
+                });
+            }
+            Ok(())
+        })
+    }
+
+    fn report(&self, error: &io::Error) {
+        if let Some(on_error) = &self.on_error {
+            on_error(error);
+        }
+    }
+
+    /// Answers the requests of one client. Requests are separated by an empty
+    /// line; the connection is closed after each request unless it asked for
+    /// `KeepAlive: on`, and when the client stays silent for the read
+    /// timeout between requests.
+    pub fn handle(&self, stream: UnixStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(self.read_timeout))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = stream;
+        loop {
+            let request = match read_request(&mut reader) {
+                Ok(request) => request,
+                Err(error) if is_timeout(&error) && error.get_ref().is_none() => {
+                    return Ok(());
+                }
+                Err(error) => return Err(error),
+            };
+            if request.is_empty() {
+                return Ok(());
+            }
+
+            if request.starts_with("COMMAND ") {
+                if let Err(errors) = self.command(&request) {
+                    for error in errors {
+                        writeln!(writer, "{}", error)?;
+                    }
+                    writer.flush()?;
+                }
+                if header(&request, "KeepAlive") != Some("on") {
+                    return Ok(());
+                }
+                continue;
+            }
+
+            let query = request.parse::<LqlQuery>();
+            let (code, body) = match &query {
+                Ok(query) => match self.status() {
+                    Ok(status) => (200, query.render(&status)),
+                    Err(error) => (500, format!("failed to read status: {}\n", error)),
+                },
+                Err(error @ LqlError::UnknownTable(_)) => (404, format!("{}\n", error)),
+                Err(error) => (400, format!("{}\n", error)),
+            };
+            if fixed16_requested(&request) {
+                write!(writer, "{}", fixed16_header(code, body.len()))?;
+            }
+            writer.write_all(body.as_bytes())?;
+            writer.flush()?;
+
+            if !query.is_ok_and(|query| query.keep_alive()) {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Writes the commands of `request`, or none of them if a line is not a
+    /// valid command.
+    fn command(&self, request: &str) -> Result<(), Vec<String>> {
+        let mut cmds: Vec<Box<dyn NagiosCmd>> = Vec::new();
+        let mut errors = Vec::new();
+        for line in request
+            .lines()
+            .filter(|line| !line.starts_with("KeepAlive:"))
+        {
+            match line.strip_prefix("COMMAND ").map(str::parse::<RawCmd>) {
+                Some(Ok(cmd)) => cmds.push(Box::new(cmd)),
+                Some(Err(error)) => errors.push(format!("invalid command {:?}: {}", line, error)),
+                None => errors.push(format!("unexpected line {:?}", line)),
+            }
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        self.nagrs
+            .write_cmds(&cmds)
+            .map_err(|error| vec![format!("failed to write commands: {}", error)])
+    }
+
+    /// The parsed status.dat, re-read if it changed since the last request.
+    /// If re-reading fails, the last good status is kept.
+    pub fn status(&self) -> anyhow::Result<Arc<NagiosStatus>> {
+        self.status.get()
+    }
+}
+
+/// Reads lines up to an empty line or the end of the stream, at most
+/// `MAX_REQUEST_LEN` bytes. Empty once the client has closed the connection.
+/// A read timeout in the middle of a request is reported as
+/// `ErrorKind::TimedOut`.
+fn read_request<R: BufRead>(reader: &mut R) -> io::Result<String> {
+    let mut limited = reader.take(MAX_REQUEST_LEN);
+    let mut request = String::new();
+    loop {
+        let mut line = String::new();
+        match limited.read_line(&mut line) {
+            Ok(0) => break,
+            Ok(_) if !line.ends_with('\n') && limited.limit() == 0 => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("request longer than {} bytes", MAX_REQUEST_LEN),
+                ));
+            }
+            Ok(_) if line.trim_end().is_empty() => break,
+            Ok(_) => request.push_str(&line),
+            Err(error) if is_timeout(&error) && !(request.is_empty() && line.is_empty()) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "client stopped sending in the middle of a request",
+                ));
+            }
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(request)
+}
+
+fn is_timeout(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
+
+/// The value of the first `name` header of `request`, looked up in the raw
+/// request so that requests that fail to parse are answered as asked too.
+fn header<'a>(request: &'a str, name: &str) -> Option<&'a str> {
+    request.lines().find_map(|line| {
+        let (header, value) = line.split_once(':')?;
+        (header == name).then(|| value.trim())
+    })
+}
+
+/// Whether `request` has `ResponseHeader: fixed16`.
+fn fixed16_requested(request: &str) -> bool {
+    header(request, "ResponseHeader") == Some("fixed16")
+}
+
+/// `ResponseHeader: fixed16`: the status code and body length, padded to
+/// 16 bytes.
+fn fixed16_header(code: u16, len: usize) -> String {
+    format!("{:03} {:>11}\n", code, len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nagios::audit::AuditLog;
+    use crate::nagios::policy::CommandPolicy;
+    use std::io::Read;
+
+    fn server(dir: &Path) -> LivestatusServer<PathBuf> {
+        let status_file_path = dir.join("status.dat");
+        fs::copy("testdata/status.dat", &status_file_path).unwrap();
+        let nagrs = Nagrs::new(dir.join("nagios.cmd"), status_file_path)
+            .with_dry_run(true)
+            .with_audit_log(AuditLog::new(dir.join("audit.jsonl")));
+        LivestatusServer::new(nagrs, dir.join("live"))
+    }
+
+    fn request(server: &LivestatusServer<PathBuf>, request: &str) -> String {
+        let (mut client, stream) = UnixStream::pair().unwrap();
+        client.write_all(request.as_bytes()).unwrap();
+        client.shutdown(std::net::Shutdown::Write).unwrap();
+        server.handle(stream).unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn test_fixed16_header() {
+        assert_eq!(fixed16_header(200, 42), "200          42\n");
+        assert_eq!(fixed16_header(200, 42).len(), 16);
+    }
+
+    #[test]
+    fn test_get() {
+        let dir = tempfile::tempdir().unwrap();
+        let server = server(dir.path());
+        struct TestCase<'a>(&'a str, &'a str);
+        let test_cases = vec![
+            TestCase("GET hosts\nColumns: name state\n\n", "localhost;0\n"),
+            TestCase(
+                "GET hosts\nColumns: name\nResponseHeader: fixed16\n\n",
+                "200          10\nlocalhost\n",
+            ),
+            TestCase(
+                "GET log\nResponseHeader: fixed16\n\n",
+                "404          19\nunknown table: log\n",
+            ),
+            TestCase(
+                "GET hosts\nColumns: nonexistent\nResponseHeader: fixed16\n\n",
+                "400          42\ntable 'hosts' has no column 'nonexistent'\n",
+            ),
+            // the connection is kept open for the second request
+            TestCase(
+                "GET hosts\nColumns: name\nKeepAlive: on\n\nGET status\nColumns: nagios_pid\n\n",
+                "localhost\n23\n",
+            ),
+            TestCase(
+                "GET hosts\nColumns: name\n\nGET status\nColumns: nagios_pid\n\n",
+                "localhost\n",
+            ),
+        ];
+        for test_case in test_cases {
+            assert_eq!(
+                request(&server, test_case.0),
+                test_case.1,
+                "{}",
+                test_case.0
+            );
+        }
+    }
+
+    #[test]
+    fn test_command() {
+        let dir = tempfile::tempdir().unwrap();
+        let server = server(dir.path());
+        let response = request(
+            &server,
+            "COMMAND [1647824400] DISABLE_HOST_CHECK;localhost\nKeepAlive: on\n\n\
+             COMMAND [1647824400] DISABLE_SVC_CHECK;localhost;HTTP\n\n\
+             COMMAND [1647824400] DISABLE_SVC_CHECK;localhost;PING\n",
+        );
+        assert_eq!(response, "");
+
+        let records = AuditLog::new(dir.path().join("audit.jsonl"))
+            .read()
+            .unwrap();
+        assert_eq!(
+            records
+                .iter()
+                .map(|record| record.command.as_str())
+                .collect::<Vec<_>>(),
+            vec![
+                "DISABLE_HOST_CHECK;localhost",
+                "DISABLE_SVC_CHECK;localhost;HTTP"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_limits() {
+        let dir = tempfile::tempdir().unwrap();
+        let server = server(dir.path()).with_read_timeout(Duration::from_millis(50));
+
+        // an idle client is disconnected
+        let (_client, stream) = UnixStream::pair().unwrap();
+        server.handle(stream).unwrap();
+
+        // as is one that stops in the middle of a request
+        let (mut client, stream) = UnixStream::pair().unwrap();
+        client.write_all(b"GET hosts\n").unwrap();
+        let error = server.handle(stream).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+
+        // and one that sends too much
+        let (mut client, stream) = UnixStream::pair().unwrap();
+        let writer = thread::spawn(move || {
+            let _ = client.write_all(&vec![b'a'; MAX_REQUEST_LEN as usize + 1]);
+        });
+        let error = server.handle(stream).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        writer.join().unwrap();
+    }
+
+    #[test]
+    fn test_command_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let server = server(dir.path());
+        struct TestCase<'a>(&'a str, &'a str);
+        let test_cases = vec![
+            TestCase(
+                "COMMAND [1647824400] DISABLE_HOST_CHECK;localhost\nCOMMAND [x] BROKEN\n",
+                "invalid command \"COMMAND [x] BROKEN\": invalid timestamp: x\n",
+            ),
+            TestCase(
+                "COMMAND [1647824400] DISABLE_HOST_CHECK;localhost\nGET hosts\n",
+                "unexpected line \"GET hosts\"\n",
+            ),
+        ];
+        for test_case in test_cases {
+            assert_eq!(
+                request(&server, test_case.0),
+                test_case.1,
+                "{}",
+                test_case.0
+            );
+        }
+        // nothing of a request with an invalid line is written
+        assert!(!dir.path().join("audit.jsonl").exists());
+
+        let nagrs = Nagrs::new(dir.path().join("nagios.cmd"), dir.path().join("status.dat"))
+            .with_dry_run(true)
+            .with_policy(CommandPolicy::deny_by_default());
+        let server = LivestatusServer::new(nagrs, dir.path().join("live"));
+        assert_eq!(
+            request(
+                &server,
+                "COMMAND [1647824400] DISABLE_HOST_CHECK;localhost\n"
+            ),
+            "failed to write commands: command denied by policy: DISABLE_HOST_CHECK;localhost\n"
+        );
+    }
+
+    #[test]
+    fn test_reload_status() {
+        let dir = tempfile::tempdir().unwrap();
+        let server = server(dir.path());
+        let status_file_path = dir.path().join("status.dat");
+        let first = server.status().unwrap();
+        assert!(Arc::ptr_eq(&first, &server.status().unwrap()));
+
+        // Nagios writes a temporary file and renames it over status.dat
+        let updated = fs::read_to_string(&status_file_path)
+            .unwrap()
+            .replace("nagios_pid=23", "nagios_pid=42");
+        let tmp_path = dir.path().join("status.dat.tmp");
+        fs::write(&tmp_path, updated).unwrap();
+        fs::rename(&tmp_path, &status_file_path).unwrap();
+        assert_eq!(
+            request(&server, "GET status\nColumns: nagios_pid\n"),
+            "42\n"
+        );
+
+        // a broken update keeps the last good status
+        fs::write(&tmp_path, "hoststatus {\nbroken\n}\n").unwrap();
+        fs::rename(&tmp_path, &status_file_path).unwrap();
+        assert_eq!(
+            request(&server, "GET status\nColumns: nagios_pid\n"),
+            "42\n"
+        );
+    }
+
+    #[test]
+    fn test_bind() {
+        let dir = tempfile::tempdir().unwrap();
+        let server = server(dir.path());
+        let socket_path = dir.path().join("live");
+
+        // a stale socket is replaced
+        drop(UnixListener::bind(&socket_path).unwrap());
+        server.bind().unwrap();
+
+        // anything else is left alone
+        let status_file_path = dir.path().join("status.dat");
+        let server = LivestatusServer::new(
+            Nagrs::new(dir.path().join("nagios.cmd"), status_file_path.clone()),
+            &status_file_path,
+        );
+        let error = server.bind().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+        assert!(NagiosStatus::parse_file(&status_file_path).is_ok());
+    }
+
+    #[test]
+    fn test_serve() {
+        let dir = tempfile::tempdir().unwrap();
+        let server = server(dir.path());
+        let socket_path = dir.path().join("live");
+        let listener = UnixListener::bind(&socket_path).unwrap();
+        thread::spawn(move || server.serve(listener));
+
+        let mut client = UnixStream::connect(&socket_path).unwrap();
+        client
+            .write_all(b"GET services\nStats: state = 0\n\n")
+            .unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        assert_eq!(response, "7\n");
+    }
+
+    #[test]
+    fn test_max_connections() {
+        let dir = tempfile::tempdir().unwrap();
+        let server = server(dir.path()).with_max_connections(1);
+        let socket_path = dir.path().join("live");
+        let listener = UnixListener::bind(&socket_path).unwrap();
+        thread::spawn(move || server.serve(listener));
+
+        let idle = UnixStream::connect(&socket_path).unwrap();
+        let mut client = UnixStream::connect(&socket_path).unwrap();
+        client
+            .write_all(b"GET status\nColumns: nagios_pid\n\n")
+            .unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_millis(100)))
+            .unwrap();
+        let mut response = String::new();
+        assert!(client.read_to_string(&mut response).is_err());
+
+        // served once the first connection closes
+        drop(idle);
+        client.set_read_timeout(None).unwrap();
+        client.read_to_string(&mut response).unwrap();
+        assert_eq!(response, "23\n");
+    }
+
+    #[test]
+    fn test_serve_error_handler() {
+        let dir = tempfile::tempdir().unwrap();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let sender = std::sync::Mutex::new(sender);
+        let server = server(dir.path()).with_error_handler(move |error| {
+            sender.lock().unwrap().send(error.kind()).unwrap();
+        });
+        let socket_path = dir.path().join("live");
+        let listener = UnixListener::bind(&socket_path).unwrap();
+        thread::spawn(move || server.serve(listener));
+
+        // a request that is not UTF-8 fails its connection only
+        let mut client = UnixStream::connect(&socket_path).unwrap();
+        client.write_all(b"GET \xff\n\n").unwrap();
+        assert_eq!(receiver.recv().unwrap(), io::ErrorKind::InvalidData);
+
+        let mut client = UnixStream::connect(&socket_path).unwrap();
+        client
+            .write_all(b"GET status\nColumns: nagios_pid\n\n")
+            .unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        assert_eq!(response, "23\n");
+    }
+}